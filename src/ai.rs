@@ -0,0 +1,335 @@
+//! A bot that plays by trying every placement of the current and held piece and keeping
+//! the one that leaves the best looking board.
+//!
+//! Placements are found by searching the moves a player could make, using the engine
+//! itself on copies of the game, so anything the bot finds (kicks and tucks included) is
+//! something the engine will accept.
+use std::collections::{HashSet, VecDeque};
+
+use crate::game::{AppState, Event, MovementType, StateChange};
+use crate::tetromino::Tetromino;
+
+/// How much each board feature counts towards a placement's score.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Weights {
+    pub height: f32,
+    pub holes: f32,
+    pub bumpiness: f32,
+    pub wells: f32,
+    pub lines: f32,
+}
+/// Weights tuned for clearing as many lines as possible, from Yiyuan Lee's genetic search,
+/// plus a light penalty on wells.
+impl Default for Weights {
+    fn default() -> Self {
+        Weights {
+            height: -0.510066,
+            holes: -0.35663,
+            bumpiness: -0.184483,
+            wells: -0.1,
+            lines: 0.760666,
+        }
+    }
+}
+impl Weights {
+    pub fn evaluate(&self, features: Features) -> f32 {
+        self.height * features.height as f32
+            + self.holes * features.holes as f32
+            + self.bumpiness * features.bumpiness as f32
+            + self.wells * features.wells as f32
+            + self.lines * features.lines as f32
+    }
+}
+
+/// What the bot looks at in a board.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Features {
+    /// Sum of the column heights.
+    pub height: u32,
+    /// Empty cells with a block somewhere above them.
+    pub holes: u32,
+    /// Sum of the height differences between neighbouring columns.
+    pub bumpiness: u32,
+    /// Sum of the depths of columns lower than both neighbours, counting walls as high.
+    pub wells: u32,
+    /// Lines cleared by the placement.
+    pub lines: u32,
+}
+impl Features {
+    pub fn of(state: &AppState, lines: u32) -> Self {
+        let (rows, cols) = (state.board.num_rows(), state.width());
+        let heights = (0..cols)
+            .map(|col| {
+                let top = (0..rows)
+                    .find(|row| state.board[(*row, col)].is_some())
+                    .unwrap_or(rows);
+                (rows - top) as u32
+            })
+            .collect::<Vec<_>>();
+        let holes = (0..cols)
+            .map(|col| {
+                (rows - heights[col] as usize..rows)
+                    .filter(|row| state.board[(*row, col)].is_none())
+                    .count() as u32
+            })
+            .sum();
+        let bumpiness = heights
+            .windows(2)
+            .map(|pair| pair[0].abs_diff(pair[1]))
+            .sum();
+        let wells = (0..cols)
+            .map(|col| {
+                let left = col.checked_sub(1).map_or(rows as u32, |col| heights[col]);
+                let right = heights.get(col + 1).copied().unwrap_or(rows as u32);
+                left.min(right).saturating_sub(heights[col])
+            })
+            .sum();
+        Features {
+            height: heights.iter().sum(),
+            holes,
+            bumpiness,
+            wells,
+            lines,
+        }
+    }
+}
+
+/// A way to finish the current turn.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Placement {
+    /// Inputs to apply, in order. The last one is always a hard drop.
+    pub inputs: Vec<StateChange>,
+    /// The piece as it locks, and where.
+    pub piece: Tetromino,
+    pub location: (isize, isize),
+    pub score: f32,
+}
+
+/// Inputs the search tries from every position. Soft drop lets it find tucks and spins
+/// under overhangs.
+const MOVES: [StateChange; 5] = [
+    StateChange::Move(MovementType::Left),
+    StateChange::Move(MovementType::Right),
+    StateChange::Rotate(MovementType::Right),
+    StateChange::Rotate(MovementType::Left),
+    StateChange::SoftDrop,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Bot {
+    pub weights: Weights,
+}
+impl Bot {
+    pub fn new(weights: Weights) -> Self {
+        Bot { weights }
+    }
+
+    /// Every distinct place the active piece, or the held one, can lock, scored.
+    pub fn placements(&self, state: &AppState) -> Vec<Placement> {
+        let mut placements = Vec::new();
+        if state.game_over {
+            return placements;
+        }
+        self.search(state, Vec::new(), &mut placements);
+        if state.can_hold {
+            let mut held = state.clone();
+            held.apply(StateChange::HoldPiece);
+            if !held.game_over {
+                self.search(&held, vec![StateChange::HoldPiece], &mut placements);
+            }
+        }
+        placements
+    }
+
+    /// The best placement, or `None` if the game is over.
+    pub fn best(&self, state: &AppState) -> Option<Placement> {
+        self.placements(state)
+            .into_iter()
+            .max_by(|a, b| a.score.total_cmp(&b.score))
+    }
+
+    /// Plays the best placement and returns everything that happened.
+    pub fn play(&self, state: &mut AppState) -> Vec<Event> {
+        let Some(placement) = self.best(state) else {
+            return Vec::new();
+        };
+        placement
+            .inputs
+            .into_iter()
+            .flat_map(|change| state.apply(change))
+            .collect()
+    }
+
+    /// Breadth first over positions of the active piece, so each landing spot is reached
+    /// with as few inputs as possible.
+    fn search(&self, start: &AppState, prefix: Vec<StateChange>, placements: &mut Vec<Placement>) {
+        let key = |state: &AppState| (state.piece.rotation, state.location);
+        let mut seen = HashSet::from([key(start)]);
+        let mut landed = HashSet::new();
+        let mut queue = VecDeque::from([(start.clone(), prefix)]);
+
+        while let Some((state, inputs)) = queue.pop_front() {
+            if landed.insert((
+                state.piece.rotation,
+                state.location.0,
+                state.ghost_location(),
+            )) {
+                let mut dropped = state.clone();
+                let events = dropped.apply(StateChange::HardDrop);
+                let score = if dropped.game_over && !dropped.completed {
+                    f32::NEG_INFINITY
+                } else {
+                    let lines = events
+                        .iter()
+                        .map(|event| match event {
+                            Event::Locked(clear) => clear.lines as u32,
+                            _ => 0,
+                        })
+                        .sum();
+                    self.weights.evaluate(Features::of(&dropped, lines))
+                };
+                let mut inputs = inputs.clone();
+                inputs.push(StateChange::HardDrop);
+                placements.push(Placement {
+                    inputs,
+                    piece: state.piece,
+                    location: (state.location.0, state.ghost_location()),
+                    score,
+                });
+            }
+
+            for change in MOVES {
+                let mut next = state.clone();
+                next.apply(change);
+                if seen.insert(key(&next)) {
+                    let mut inputs = inputs.clone();
+                    inputs.push(change);
+                    queue.push_back((next, inputs));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{Cell, Config};
+    use crate::tetromino::{Piece, Rotation};
+
+    /// The first seeded game that opens with `piece`.
+    fn game_starting_with(piece: Piece) -> AppState {
+        (0..)
+            .map(|seed| AppState::from_seed(Config::default(), seed))
+            .find(|state| state.piece.piece == piece)
+            .unwrap()
+    }
+
+    fn fill(state: &mut AppState, cells: impl IntoIterator<Item = (usize, usize)>) {
+        for cell in cells {
+            state.board[cell] = Some(Cell::Garbage);
+        }
+    }
+
+    fn find(placements: &[Placement], piece: Tetromino, location: (isize, isize)) -> &Placement {
+        placements
+            .iter()
+            .find(|placement| placement.piece == piece && placement.location == location)
+            .unwrap_or_else(|| panic!("no placement of {piece:?} at {location:?}"))
+    }
+
+    #[test]
+    fn placements_include_tucks_under_overhangs() {
+        // A roof over the left of the bottom two rows, which an O can only slide under
+        let mut state = game_starting_with(Piece::O);
+        fill(&mut state, (0..6).map(|col| (37, col)));
+        let placements = Bot::default().placements(&state);
+        let o = Tetromino {
+            piece: Piece::O,
+            rotation: Rotation::Up,
+        };
+        let tuck = find(&placements, o, (0, 38));
+        assert!(tuck.inputs.contains(&StateChange::SoftDrop));
+        assert!(tuck.inputs.contains(&StateChange::Move(MovementType::Left)));
+    }
+
+    #[test]
+    fn placements_include_spots_only_kicks_reach() {
+        // Only the last kick of a turn to the right gets the T into the slot
+        let mut state = game_starting_with(Piece::T);
+        fill(&mut state, [(35, 4), (37, 5), (37, 3), (39, 3)]);
+        let placements = Bot::default().placements(&state);
+        let t = Tetromino {
+            piece: Piece::T,
+            rotation: Rotation::Right,
+        };
+        let kicked = find(&placements, t, (3, 37));
+        let events = kicked
+            .inputs
+            .iter()
+            .flat_map(|change| state.apply(*change))
+            .collect::<Vec<_>>();
+        assert!(events.contains(&Event::Rotated { kick: 4 }), "{events:?}");
+    }
+
+    #[test]
+    fn placements_replay_to_where_they_say() {
+        let mut state = game_starting_with(Piece::T);
+        fill(&mut state, (0..6).map(|col| (37, col)));
+        fill(&mut state, [(39, 8), (39, 9), (38, 9)]);
+        let placements = Bot::default().placements(&state);
+        assert!(!placements.is_empty());
+        for placement in placements {
+            let (last, moves) = placement.inputs.split_last().unwrap();
+            assert_eq!(*last, StateChange::HardDrop);
+            assert!(!moves.contains(&StateChange::HardDrop));
+            let mut moved = state.clone();
+            for change in moves {
+                moved.apply(*change);
+            }
+            assert_eq!(moved.piece, placement.piece, "{:?}", placement.inputs);
+            let location = (moved.location.0, moved.ghost_location());
+            assert_eq!(location, placement.location, "{:?}", placement.inputs);
+        }
+    }
+
+    #[test]
+    fn placements_include_the_held_piece() {
+        let state = game_starting_with(Piece::T);
+        let next = state.queue[0];
+        let placements = Bot::default().placements(&state);
+        let held = placements
+            .iter()
+            .filter(|placement| placement.inputs[0] == StateChange::HoldPiece)
+            .collect::<Vec<_>>();
+        assert!(!held.is_empty());
+        assert!(held.iter().all(|placement| placement.piece.piece == next));
+
+        let mut state = state;
+        state.can_hold = false;
+        let placements = Bot::default().placements(&state);
+        assert!(placements
+            .iter()
+            .all(|placement| placement.inputs[0] != StateChange::HoldPiece));
+    }
+
+    #[test]
+    fn the_best_placement_takes_a_tetris() {
+        let mut state = game_starting_with(Piece::I);
+        fill(
+            &mut state,
+            (36..40).flat_map(|row| (0..9).map(move |col| (row, col))),
+        );
+        let best = Bot::default().best(&state).unwrap();
+        let events = best
+            .inputs
+            .into_iter()
+            .flat_map(|change| state.apply(change))
+            .collect::<Vec<_>>();
+        let lines = events.iter().find_map(|event| match event {
+            Event::Locked(clear) => Some(clear.lines),
+            _ => None,
+        });
+        assert_eq!(lines, Some(4));
+    }
+}
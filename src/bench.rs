@@ -0,0 +1,55 @@
+use std::time::Instant;
+use tetris::{AppState, Config, Loss};
+
+use crate::session::Driver;
+
+/// Pieces after which a benchmark game is stopped, since the bot can play for ever.
+const MAX_PIECES: u32 = 1000;
+/// Frames the clock runs between pieces, so gravity and timed modes still count.
+const FRAMES_PER_PIECE: u32 = 15;
+
+/// Has the bot play `games` games of `config` without a window, seeded from `first_seed`
+/// upwards, and prints how each went. Every bot sees the same pieces for the same seeds,
+/// so runs can be compared.
+pub(crate) fn run(driver: &mut Driver, games: u64, first_seed: u64, config: Config) {
+    println!("Benchmarking {}", driver.name());
+    let started = Instant::now();
+    let (mut total_pieces, mut total_lines) = (0, 0);
+    for seed in (0..games).map(|game| first_seed.wrapping_add(game)) {
+        let mut state = AppState::from_seed(config.clone(), seed);
+        let mut pieces = 0;
+        if let Err(err) = driver.start(&state) {
+            eprintln!("{} failed: {err}", driver.name());
+            return;
+        }
+        while !state.game_over && pieces < MAX_PIECES {
+            if let Err(err) = driver.play(&mut state) {
+                eprintln!("{} failed on seed {seed}: {err}", driver.name());
+                return;
+            }
+            for _ in 0..FRAMES_PER_PIECE {
+                state.tick();
+            }
+            pieces += 1;
+        }
+        let end = match state.loss {
+            _ if state.completed => "completed",
+            Some(Loss::BlockOut) => "blocked out",
+            Some(Loss::LockOut) => "locked out",
+            Some(Loss::TopOut) => "topped out",
+            None => "stopped",
+        };
+        println!(
+            "seed {seed}: {pieces} pieces, {} lines, score {}, {end}",
+            state.lines, state.score
+        );
+        total_pieces += pieces;
+        total_lines += state.lines;
+    }
+    let elapsed = started.elapsed();
+    println!(
+        "{games} games, {total_pieces} pieces, {total_lines} lines in {:.2}s ({:.0} pieces/s)",
+        elapsed.as_secs_f32(),
+        total_pieces as f32 / elapsed.as_secs_f32(),
+    );
+}
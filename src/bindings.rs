@@ -0,0 +1,437 @@
+use serde::{de::DeserializeOwned, Deserialize};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+use tetris::{Handling, MovementType, StateChange};
+use thiserror::Error;
+use winit::keyboard::{KeyCode, PhysicalKey};
+
+/// Something a key can be bound to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Action {
+    Game(StateChange),
+    Pause,
+    Restart,
+}
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::Game(change) => change.fmt(f),
+            Action::Pause => f.write_str("pause"),
+            Action::Restart => f.write_str("restart"),
+        }
+    }
+}
+impl FromStr for Action {
+    type Err = BindingsError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pause" => Ok(Action::Pause),
+            "restart" => Ok(Action::Restart),
+            _ => s
+                .parse()
+                .map(Action::Game)
+                .map_err(|_| BindingsError::UnknownAction(s.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub(crate) enum BindingsError {
+    #[error("could not read {}: {source}", path.display())]
+    Io { path: PathBuf, source: io::Error },
+    #[error("could not parse {}: {source}", path.display())]
+    Parse {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+    #[error("unknown action {0:?}")]
+    UnknownAction(String),
+    #[error("{key:?} is bound to both {first} and {second}")]
+    Conflict {
+        key: KeyCode,
+        first: Action,
+        second: Action,
+    },
+    #[error("{key:?} is bound for both player {first} and player {second}")]
+    SharedKey {
+        key: KeyCode,
+        first: usize,
+        second: usize,
+    },
+}
+
+/// The controls file as written by the player. Every field is optional and falls back to
+/// the defaults.
+///
+/// ```toml
+/// [bindings]
+/// rotate-right = ["ArrowUp", "KeyX"]
+/// hold = ["KeyC"]
+///
+/// [handling]
+/// das-ms = 133
+/// arr-ms = 0
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ControlsFile {
+    /// Keys per action. An action listed here loses its default keys.
+    bindings: BTreeMap<String, Vec<KeyCode>>,
+    handling: HandlingFile,
+}
+/// The controls file for a local match, with one table per player. Players missing
+/// from the file use the defaults for their seat.
+///
+/// ```toml
+/// [[player]]
+/// bindings = { hard-drop = ["KeyW"] }
+///
+/// [[player]]
+/// handling = { das-ms = 100 }
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct VersusFile {
+    player: Vec<ControlsFile>,
+}
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+struct HandlingFile {
+    das_ms: Option<u64>,
+    arr_ms: Option<u64>,
+    soft_drop_ms: Option<u64>,
+}
+
+/// Maps physical keys to actions, so the layout stays the same on AZERTY or Dvorak.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Bindings {
+    keys: HashMap<KeyCode, Action>,
+    pub(crate) handling: Handling,
+}
+impl Bindings {
+    /// Reads `controls.toml` from the user's config directory, or uses the defaults if
+    /// there isn't one.
+    pub(crate) fn load() -> Result<Self, BindingsError> {
+        match dirs::config_dir() {
+            Some(dir) => Self::load_from(&dir.join("tetris").join("controls.toml")),
+            None => Self::from_actions(default_actions(), Handling::default()),
+        }
+    }
+    pub(crate) fn load_from(path: &Path) -> Result<Self, BindingsError> {
+        let file: ControlsFile = read_file(path)?;
+        Self::from_file(file, default_actions())
+    }
+    /// Reads `versus.toml` from the user's config directory. There are at least two
+    /// players, and more if the file lists more.
+    pub(crate) fn load_versus() -> Result<Vec<Self>, BindingsError> {
+        match dirs::config_dir() {
+            Some(dir) => {
+                Self::from_versus_file(read_file(&dir.join("tetris").join("versus.toml"))?)
+            }
+            None => Self::from_versus_file(VersusFile::default()),
+        }
+    }
+    fn from_versus_file(file: VersusFile) -> Result<Vec<Self>, BindingsError> {
+        let mut files = file.player.into_iter();
+        let players = (0..files.len().max(2))
+            .map(|seat| {
+                let file = files.next().unwrap_or_default();
+                Self::from_file(file, versus_actions(seat))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // A key can only belong to one player
+        let mut owners = HashMap::new();
+        for (seat, bindings) in players.iter().enumerate() {
+            for key in bindings.keys.keys() {
+                if let Some(first) = owners.insert(*key, seat) {
+                    return Err(BindingsError::SharedKey {
+                        key: *key,
+                        first: first + 1,
+                        second: seat + 1,
+                    });
+                }
+            }
+        }
+        Ok(players)
+    }
+    fn from_file(
+        file: ControlsFile,
+        mut actions: BTreeMap<Action, Vec<KeyCode>>,
+    ) -> Result<Self, BindingsError> {
+        for (action, keys) in file.bindings {
+            actions.insert(action.parse()?, keys);
+        }
+        let defaults = Handling::default();
+        let handling = Handling {
+            das: file
+                .handling
+                .das_ms
+                .map_or(defaults.das, Duration::from_millis),
+            arr: file
+                .handling
+                .arr_ms
+                .map_or(defaults.arr, Duration::from_millis),
+            soft_drop: file
+                .handling
+                .soft_drop_ms
+                .map_or(defaults.soft_drop, Duration::from_millis),
+        };
+        Self::from_actions(actions, handling)
+    }
+    fn from_actions(
+        actions: BTreeMap<Action, Vec<KeyCode>>,
+        handling: Handling,
+    ) -> Result<Self, BindingsError> {
+        let mut keys = HashMap::new();
+        for (action, codes) in actions {
+            for key in codes {
+                match keys.insert(key, action) {
+                    Some(first) if first != action => {
+                        return Err(BindingsError::Conflict {
+                            key,
+                            first,
+                            second: action,
+                        })
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(Bindings { keys, handling })
+    }
+    pub(crate) fn action(&self, key: PhysicalKey) -> Option<Action> {
+        match key {
+            PhysicalKey::Code(code) => self.keys.get(&code).copied(),
+            PhysicalKey::Unidentified(_) => None,
+        }
+    }
+}
+
+/// The keys any player has bound to `action`, for help text, such as `P/Esc`. `None` if
+/// nothing is bound to it.
+pub(crate) fn key_names(bindings: &[Bindings], action: Action) -> Option<String> {
+    let keys = bindings
+        .iter()
+        .flat_map(|bindings| &bindings.keys)
+        .filter(|(_, bound)| **bound == action)
+        .map(|(key, _)| *key)
+        .collect::<BTreeSet<_>>();
+    let names = keys.into_iter().map(key_name).collect::<Vec<_>>();
+    (!names.is_empty()).then(|| names.join("/"))
+}
+/// A short name for `key`, such as `R`, `1` or `Esc`.
+fn key_name(key: KeyCode) -> String {
+    let name = format!("{key:?}");
+    match name.as_str() {
+        "Escape" => "Esc".to_string(),
+        _ => name
+            .strip_prefix("Key")
+            .or_else(|| name.strip_prefix("Digit"))
+            .unwrap_or(&name)
+            .to_string(),
+    }
+}
+
+fn read_file<T: DeserializeOwned + Default>(path: &Path) -> Result<T, BindingsError> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(T::default()),
+        Err(source) => {
+            return Err(BindingsError::Io {
+                path: path.to_path_buf(),
+                source,
+            })
+        }
+    };
+    toml::from_str(&text).map_err(|source| BindingsError::Parse {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+/// Default keys for each seat of a local match. The first player is on the left of
+/// the keyboard and also has pause and restart, the second is on the arrows. Seats
+/// past the second have no keys until the controls file gives them some.
+fn versus_actions(seat: usize) -> BTreeMap<Action, Vec<KeyCode>> {
+    let keys = match seat {
+        0 => [
+            KeyCode::KeyE,
+            KeyCode::KeyQ,
+            KeyCode::KeyA,
+            KeyCode::KeyD,
+            KeyCode::KeyS,
+            KeyCode::KeyW,
+            KeyCode::ShiftLeft,
+        ],
+        1 => [
+            KeyCode::Slash,
+            KeyCode::Period,
+            KeyCode::ArrowLeft,
+            KeyCode::ArrowRight,
+            KeyCode::ArrowDown,
+            KeyCode::ArrowUp,
+            KeyCode::ShiftRight,
+        ],
+        _ => return BTreeMap::new(),
+    };
+    let changes = [
+        StateChange::Rotate(MovementType::Right),
+        StateChange::Rotate(MovementType::Left),
+        StateChange::Move(MovementType::Left),
+        StateChange::Move(MovementType::Right),
+        StateChange::SoftDrop,
+        StateChange::HardDrop,
+        StateChange::HoldPiece,
+    ];
+    let mut actions = changes
+        .into_iter()
+        .zip(keys)
+        .map(|(change, key)| (Action::Game(change), vec![key]))
+        .collect::<BTreeMap<_, _>>();
+    if seat == 0 {
+        actions.insert(Action::Pause, vec![KeyCode::Escape, KeyCode::KeyP]);
+        actions.insert(Action::Restart, vec![KeyCode::KeyR]);
+    }
+    actions
+}
+
+fn default_actions() -> BTreeMap<Action, Vec<KeyCode>> {
+    BTreeMap::from([
+        (
+            Action::Game(StateChange::Rotate(MovementType::Right)),
+            vec![KeyCode::ArrowUp, KeyCode::KeyX],
+        ),
+        (
+            Action::Game(StateChange::Rotate(MovementType::Left)),
+            vec![KeyCode::ControlLeft, KeyCode::ControlRight, KeyCode::KeyZ],
+        ),
+        (
+            Action::Game(StateChange::Move(MovementType::Left)),
+            vec![KeyCode::ArrowLeft],
+        ),
+        (
+            Action::Game(StateChange::Move(MovementType::Right)),
+            vec![KeyCode::ArrowRight],
+        ),
+        (
+            Action::Game(StateChange::SoftDrop),
+            vec![KeyCode::ArrowDown],
+        ),
+        (Action::Game(StateChange::HardDrop), vec![KeyCode::Space]),
+        (
+            Action::Game(StateChange::HoldPiece),
+            vec![KeyCode::ShiftLeft, KeyCode::ShiftRight, KeyCode::KeyC],
+        ),
+        (Action::Pause, vec![KeyCode::Escape, KeyCode::KeyP]),
+        (Action::Restart, vec![KeyCode::KeyR]),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn controls(text: &str) -> Result<Bindings, BindingsError> {
+        Bindings::from_file(toml::from_str(text).unwrap(), default_actions())
+    }
+    fn versus(text: &str) -> Result<Vec<Bindings>, BindingsError> {
+        Bindings::from_versus_file(toml::from_str(text).unwrap())
+    }
+    fn action(bindings: &Bindings, key: KeyCode) -> Option<Action> {
+        bindings.action(PhysicalKey::Code(key))
+    }
+
+    #[test]
+    fn listed_actions_lose_their_default_keys() {
+        let bindings = controls("[bindings]\nhard-drop = [\"KeyW\"]\n").unwrap();
+        let hard_drop = Action::Game(StateChange::HardDrop);
+        assert_eq!(action(&bindings, KeyCode::KeyW), Some(hard_drop));
+        assert_eq!(action(&bindings, KeyCode::Space), None);
+        // Actions left out keep theirs
+        assert_eq!(
+            action(&bindings, KeyCode::ArrowLeft),
+            Some(Action::Game(StateChange::Move(MovementType::Left)))
+        );
+    }
+
+    #[test]
+    fn a_key_bound_to_two_actions_is_a_conflict() {
+        // X rotates right by default
+        let err = controls("[bindings]\nhard-drop = [\"KeyX\"]\n").unwrap_err();
+        assert!(
+            matches!(
+                err,
+                BindingsError::Conflict {
+                    key: KeyCode::KeyX,
+                    ..
+                }
+            ),
+            "{err}"
+        );
+        // Moving the rotation off X frees it up
+        let text = "[bindings]\nhard-drop = [\"KeyX\"]\nrotate-right = [\"ArrowUp\"]\n";
+        assert!(controls(text).is_ok());
+    }
+
+    #[test]
+    fn versus_players_cannot_share_keys() {
+        let players = versus("").unwrap();
+        assert_eq!(players.len(), 2);
+
+        // The up arrow is the second player's hard drop by default
+        let text = "[[player]]\nbindings = { hard-drop = [\"ArrowUp\"] }\n";
+        let err = versus(text).unwrap_err();
+        assert!(
+            matches!(
+                err,
+                BindingsError::SharedKey {
+                    key: KeyCode::ArrowUp,
+                    first: 1,
+                    second: 2
+                }
+            ),
+            "{err}"
+        );
+
+        // Extra seats start with no keys, so they only clash once given some
+        let text = "[[player]]\n[[player]]\n[[player]]\nbindings = { hard-drop = [\"KeyW\"] }\n";
+        let err = versus(text).unwrap_err();
+        assert!(
+            matches!(
+                err,
+                BindingsError::SharedKey {
+                    key: KeyCode::KeyW,
+                    first: 1,
+                    second: 3
+                }
+            ),
+            "{err}"
+        );
+        let text = "[[player]]\n[[player]]\n[[player]]\nbindings = { hard-drop = [\"KeyM\"] }\n";
+        assert_eq!(versus(text).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn help_text_names_the_bound_keys() {
+        let bindings = [controls("").unwrap()];
+        assert_eq!(key_names(&bindings, Action::Restart).as_deref(), Some("R"));
+        assert_eq!(
+            key_names(&bindings, Action::Pause).as_deref(),
+            Some("P/Esc")
+        );
+
+        let text = "[bindings]\nrestart = [\"F5\", \"Digit1\"]\npause = []\n";
+        let bindings = [controls(text).unwrap()];
+        assert_eq!(
+            key_names(&bindings, Action::Restart).as_deref(),
+            Some("1/F5")
+        );
+        assert_eq!(key_names(&bindings, Action::Pause), None);
+    }
+}
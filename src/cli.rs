@@ -0,0 +1,186 @@
+use std::path::PathBuf;
+use tetris::{Mode, RandomizerKind};
+use thiserror::Error;
+
+pub(crate) const USAGE: &str =
+    "Usage: tetris [--mode <marathon|sprint[-<lines>]|ultra[-<seconds>]|cheese[-<lines>]>]
+              [--hole-change <0 to 1>] [--cheese-height <rows>] [--board <width>x<height>]
+              [--pieces <piece set file>] [--randomizer <7-bag|14-bag|random|tgm|nes>]
+              [--versus]
+              [--host <address>] [--join <address>]
+              [--ai] [--tbp <bot command>] [--bench <games>] [--seed <number>]
+              [--name <player>]
+              [--record <replay file>] [--replay <replay file>]";
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct Options {
+    pub(crate) mode: Mode,
+    /// Chance that a cheese row's hole moves. The engine default is used if this is missing.
+    pub(crate) hole_change: Option<f64>,
+    /// Garbage rows kept on the board in cheese mode. The engine default is used if this
+    /// is missing.
+    pub(crate) cheese_height: Option<usize>,
+    /// Width and height of the board. The engine default is used if this is missing.
+    pub(crate) board: Option<(usize, usize)>,
+    /// File of pieces to play with instead of the standard tetrominoes.
+    pub(crate) pieces: Option<PathBuf>,
+    /// How pieces are dealt. The mode's randomizer is used if this is missing.
+    pub(crate) randomizer: Option<RandomizerKind>,
+    /// Seed for the piece randomizer. A random seed is used if this is missing.
+    pub(crate) seed: Option<u64>,
+    /// Where to save a recording of the game. Games after a restart are saved beside it
+    /// as `<name>-2`, `<name>-3` and so on.
+    pub(crate) record: Option<PathBuf>,
+    /// A recording to watch instead of playing.
+    pub(crate) replay: Option<PathBuf>,
+    /// Play a local match with a board per player.
+    pub(crate) versus: bool,
+    /// Address to wait for an opponent on.
+    pub(crate) host: Option<String>,
+    /// Address of an opponent to play.
+    pub(crate) join: Option<String>,
+    /// Watch the bot play.
+    pub(crate) ai: bool,
+    /// Command line of an external TBP bot, to watch or benchmark instead of the built-in
+    /// one.
+    pub(crate) tbp: Option<Vec<String>>,
+    /// Have the bot play this many games without a window and report how it did.
+    pub(crate) bench: Option<u64>,
+    /// Name to put in the high score table.
+    pub(crate) name: Option<String>,
+}
+
+#[derive(Debug, Error)]
+pub(crate) enum CliError {
+    #[error("missing value for {0}")]
+    MissingValue(String),
+    #[error("invalid value for {flag}: {value}")]
+    InvalidValue { flag: String, value: String },
+    #[error("unknown argument {0}")]
+    Unknown(String),
+    #[error("{0} can't be used with {1}")]
+    Conflict(&'static str, &'static str),
+}
+
+pub(crate) fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Options, CliError> {
+    let mut options = Options::default();
+    while let Some(flag) = args.next() {
+        match flag.as_str() {
+            "--mode" => {
+                let value = args.next().ok_or(CliError::MissingValue(flag.clone()))?;
+                options.mode = value
+                    .parse()
+                    .map_err(|_| CliError::InvalidValue { flag, value })?;
+            }
+            "--hole-change" => {
+                let value = args.next().ok_or(CliError::MissingValue(flag.clone()))?;
+                match value.parse() {
+                    Ok(chance) if (0.0..=1.0).contains(&chance) => {
+                        options.hole_change = Some(chance)
+                    }
+                    _ => return Err(CliError::InvalidValue { flag, value }),
+                }
+            }
+            "--cheese-height" => {
+                let value = args.next().ok_or(CliError::MissingValue(flag.clone()))?;
+                match value.parse() {
+                    Ok(rows) if rows >= 1 => options.cheese_height = Some(rows),
+                    _ => return Err(CliError::InvalidValue { flag, value }),
+                }
+            }
+            "--board" => {
+                let value = args.next().ok_or(CliError::MissingValue(flag.clone()))?;
+                let size = value
+                    .split_once('x')
+                    .and_then(|(width, height)| Some((width.parse().ok()?, height.parse().ok()?)))
+                    .filter(|(width, height)| *width >= 4 && *height >= 4);
+                match size {
+                    Some(size) => options.board = Some(size),
+                    None => return Err(CliError::InvalidValue { flag, value }),
+                }
+            }
+            "--pieces" => {
+                let value = args.next().ok_or(CliError::MissingValue(flag))?;
+                options.pieces = Some(value.into());
+            }
+            "--randomizer" => {
+                let value = args.next().ok_or(CliError::MissingValue(flag.clone()))?;
+                let randomizer = value
+                    .parse()
+                    .map_err(|_| CliError::InvalidValue { flag, value })?;
+                options.randomizer = Some(randomizer);
+            }
+            "--seed" => {
+                let value = args.next().ok_or(CliError::MissingValue(flag.clone()))?;
+                let seed = value
+                    .parse()
+                    .map_err(|_| CliError::InvalidValue { flag, value })?;
+                options.seed = Some(seed);
+            }
+            "--name" => {
+                let value = args.next().ok_or(CliError::MissingValue(flag))?;
+                options.name = Some(value);
+            }
+            "--record" => {
+                let value = args.next().ok_or(CliError::MissingValue(flag))?;
+                options.record = Some(value.into());
+            }
+            "--replay" => {
+                let value = args.next().ok_or(CliError::MissingValue(flag))?;
+                options.replay = Some(value.into());
+            }
+            "--versus" => options.versus = true,
+            "--ai" => options.ai = true,
+            "--tbp" => {
+                let value = args.next().ok_or(CliError::MissingValue(flag.clone()))?;
+                let command = value
+                    .split_whitespace()
+                    .map(str::to_string)
+                    .collect::<Vec<_>>();
+                if command.is_empty() {
+                    return Err(CliError::InvalidValue { flag, value });
+                }
+                options.tbp = Some(command);
+            }
+            "--bench" => {
+                let value = args.next().ok_or(CliError::MissingValue(flag.clone()))?;
+                let games = value
+                    .parse()
+                    .map_err(|_| CliError::InvalidValue { flag, value })?;
+                options.bench = Some(games);
+            }
+            "--host" => {
+                let value = args.next().ok_or(CliError::MissingValue(flag))?;
+                options.host = Some(value);
+            }
+            "--join" => {
+                let value = args.next().ok_or(CliError::MissingValue(flag))?;
+                options.join = Some(value);
+            }
+            _ => return Err(CliError::Unknown(flag)),
+        }
+    }
+    // One way to play at a time, and only single player games are recorded. --tbp picks
+    // the bot for --bench, and is a way to play of its own otherwise.
+    let matches = [
+        ("--versus", options.versus),
+        ("--host", options.host.is_some()),
+        ("--join", options.join.is_some()),
+        ("--ai", options.ai),
+        ("--tbp", options.tbp.is_some() && options.bench.is_none()),
+        ("--bench", options.bench.is_some()),
+    ];
+    let mut chosen = matches
+        .into_iter()
+        .chain([("--replay", options.replay.is_some())])
+        .filter_map(|(flag, set)| set.then_some(flag));
+    if let (Some(first), Some(second)) = (chosen.next(), chosen.next()) {
+        return Err(CliError::Conflict(first, second));
+    }
+    if let Some((flag, _)) = matches.into_iter().find(|(_, set)| *set) {
+        if options.record.is_some() {
+            return Err(CliError::Conflict("--record", flag));
+        }
+    }
+    Ok(options)
+}
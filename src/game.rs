@@ -0,0 +1,1383 @@
+use array2d::Array2D;
+use rand::{random, Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use std::{collections::VecDeque, fmt, str::FromStr, sync::Arc, time::Duration};
+use thiserror::Error;
+
+use crate::mode::Mode;
+use crate::pieces::PieceSet;
+use crate::randomizer::{Randomizer, RandomizerKind};
+use crate::scoring::{Clear, TSpin};
+use crate::tetromino::{Piece, Rotation, Tetromino};
+
+/// Board height unless a [`Config`] says otherwise.
+pub const ROWS: usize = 20;
+/// Board width unless a [`Config`] says otherwise.
+pub const COLS: usize = 10;
+/// Length of one engine frame. [`AppState::tick`] advances the game clock by this much.
+pub const FRAME: Duration = Duration::from_nanos(1_000_000_000 / 60);
+/// Lines to clear before the level goes up.
+pub const LINES_PER_LEVEL: u32 = 10;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    /// How long a piece may rest on the stack before it locks.
+    pub lock_delay: Duration,
+    /// How many successful moves or rotations may restart the lock delay
+    /// before the piece has to fall below its lowest row so far.
+    pub lock_resets: u32,
+    pub start_level: u32,
+    /// Gravity in cells per frame, indexed by level starting at level 1.
+    /// Levels past the end of the table use the last entry. Usually the mode's
+    /// [`Mode::gravity`].
+    pub gravity: Vec<f32>,
+    /// How many upcoming pieces are known ahead of time.
+    pub preview: usize,
+    pub mode: Mode,
+    /// Chance that a new garbage row moves its hole instead of lining it up with the
+    /// row below.
+    pub hole_change: f64,
+    /// Garbage rows kept on the board in cheese mode while there are any left to dig.
+    pub cheese_height: usize,
+    /// Size of the visible field in cells. Pieces need at least 4 of each.
+    pub width: usize,
+    pub height: usize,
+    /// Hidden rows above the visible field that pieces spawn in and can be stacked into.
+    /// Needs at least 2 for pieces to spawn.
+    pub buffer: usize,
+    /// The pieces dealt, and how they look and rotate.
+    pub pieces: Arc<PieceSet>,
+    /// How pieces are dealt. Usually the mode's [`Mode::randomizer`].
+    pub randomizer: RandomizerKind,
+}
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            lock_delay: Duration::from_millis(500),
+            lock_resets: 15,
+            start_level: 1,
+            gravity: Mode::default().gravity(),
+            preview: 5,
+            mode: Mode::default(),
+            hole_change: 0.3,
+            cheese_height: 10,
+            width: COLS,
+            height: ROWS,
+            buffer: ROWS,
+            pieces: PieceSet::tetrominoes(),
+            randomizer: Mode::default().randomizer(),
+        }
+    }
+}
+
+/// The guideline curve: `(0.8 - (level - 1) * 0.007) ^ (level - 1)` seconds per row,
+/// passing 20G by level 20. [`AppState::gravity`] caps it at the board's height.
+pub fn guideline_gravity() -> Vec<f32> {
+    (1..=20)
+        .map(|level| {
+            let seconds = (0.8 - (level - 1) as f32 * 0.007).powi(level - 1);
+            1.0 / (seconds * 60.0)
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum StateChange {
+    Rotate(MovementType),
+    HoldPiece,
+    HardDrop,
+    SoftDrop,
+    Move(MovementType),
+}
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum MovementType {
+    Right,
+    Left,
+}
+impl fmt::Display for StateChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            StateChange::Rotate(MovementType::Right) => "rotate-right",
+            StateChange::Rotate(MovementType::Left) => "rotate-left",
+            StateChange::HoldPiece => "hold",
+            StateChange::HardDrop => "hard-drop",
+            StateChange::SoftDrop => "soft-drop",
+            StateChange::Move(MovementType::Right) => "move-right",
+            StateChange::Move(MovementType::Left) => "move-left",
+        })
+    }
+}
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("unknown action {0:?}")]
+pub struct ParseStateChangeError(pub String);
+impl FromStr for StateChange {
+    type Err = ParseStateChangeError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "rotate-right" => StateChange::Rotate(MovementType::Right),
+            "rotate-left" => StateChange::Rotate(MovementType::Left),
+            "hold" => StateChange::HoldPiece,
+            "hard-drop" => StateChange::HardDrop,
+            "soft-drop" => StateChange::SoftDrop,
+            "move-right" => StateChange::Move(MovementType::Right),
+            "move-left" => StateChange::Move(MovementType::Left),
+            _ => return Err(ParseStateChangeError(s.to_string())),
+        })
+    }
+}
+
+/// What fills an occupied cell of the board.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cell {
+    /// Part of a locked piece.
+    Block(Piece),
+    /// Part of a garbage row.
+    Garbage,
+}
+impl Cell {
+    pub fn to_rgb(self, pieces: &PieceSet) -> (u8, u8, u8) {
+        match self {
+            Cell::Block(piece) => pieces.color(piece),
+            Cell::Garbage => (128, 128, 128),
+        }
+    }
+}
+
+/// Something that happened while applying a [`StateChange`] or a gravity tick.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    /// The active piece rotated using the `kick`th offset of its kick table.
+    /// Kick 0 is the unkicked rotation.
+    Rotated { kick: usize },
+    /// The active piece was locked into the board.
+    Locked(Clear),
+    /// A new active piece entered the field.
+    Spawned(Piece),
+    /// A line clear sent this many garbage lines, after cancelling any that were
+    /// waiting to rise.
+    Attack(u32),
+    /// The game ended, by topping out or by reaching the mode's goal.
+    /// No further changes are accepted.
+    GameOver,
+}
+
+/// Why a game was lost.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Loss {
+    /// A new piece spawned overlapping the stack.
+    BlockOut,
+    /// A piece locked entirely inside the hidden buffer.
+    LockOut,
+    /// Garbage pushed the stack out of the top of the buffer.
+    TopOut,
+}
+
+#[derive(Debug, Clone)]
+pub struct AppState {
+    /// The hidden buffer rows followed by the visible field, top row first.
+    pub board: Array2D<Option<Cell>>,
+    pub piece: Tetromino,
+    /// Column and board row of the top left of the active piece.
+    pub location: (isize, isize),
+    /// The game has ended, either because of `loss` or because `completed` is set.
+    pub game_over: bool,
+    /// How the game was lost, if it was.
+    pub loss: Option<Loss>,
+    /// The mode's goal was reached, or its time ran out.
+    pub completed: bool,
+    pub score: u64,
+    /// Frames the game has been running for.
+    pub frame: u64,
+    /// The frame of the first input, which starts the timer.
+    pub started: Option<u64>,
+    pub level: u32,
+    pub lines: u32,
+    /// Garbage rows cleared so far.
+    pub garbage_cleared: u32,
+    /// Garbage lines received from an opponent that rise on the next lock that
+    /// doesn't clear lines.
+    pub pending_garbage: u32,
+    /// Consecutive line-clearing locks after the first, or `None` if the last lock cleared nothing.
+    pub combo: Option<u32>,
+    /// The last line clear was difficult, so the next difficult one scores back-to-back.
+    pub back_to_back: bool,
+    /// Line clears that left the board empty.
+    pub perfect_clears: u32,
+    /// The frame of the last perfect clear, so frontends can celebrate it for a while.
+    pub last_perfect_clear: Option<u64>,
+    pub held: Option<Piece>,
+    /// Upcoming pieces, next one first.
+    pub queue: VecDeque<Piece>,
+    pub can_hold: bool,
+    pub config: Config,
+    /// Seed of the piece randomizer. The same seed and inputs replay the same game.
+    pub seed: u64,
+    /// Time the active piece has spent resting on the stack, if it is resting.
+    pub lock_timer: Option<Duration>,
+    lock_resets: u32,
+    /// The kick used by the last rotation, if the piece hasn't moved since.
+    last_rotation: Option<usize>,
+    lowest_row: isize,
+    /// Fraction of a row gravity has pulled the active piece so far.
+    gravity_progress: f32,
+    randomizer: Box<dyn Randomizer>,
+    rng: ChaCha8Rng,
+    /// Column of the hole in the last garbage row added.
+    garbage_hole: Option<usize>,
+    /// Kept apart from `rng` so garbage doesn't change which pieces come.
+    garbage_rng: ChaCha8Rng,
+    events: Vec<Event>,
+}
+impl AppState {
+    pub fn new() -> Self {
+        Self::with_config(Config::default())
+    }
+    pub fn with_config(config: Config) -> Self {
+        Self::from_seed(config, random())
+    }
+    /// Starts a game whose pieces are fully determined by `seed`.
+    pub fn from_seed(config: Config, seed: u64) -> Self {
+        let mut tmp = AppState {
+            board: Array2D::filled_with(None, config.buffer + config.height, config.width),
+            piece: Tetromino {
+                piece: config.pieces.pieces()[0],
+                rotation: Rotation::default(),
+            },
+            location: (0, 0),
+            randomizer: config.randomizer.build(config.pieces.pieces()),
+            game_over: false,
+            loss: None,
+            completed: false,
+            score: 0,
+            frame: 0,
+            started: None,
+            level: config.start_level,
+            lines: 0,
+            garbage_cleared: 0,
+            pending_garbage: 0,
+            combo: None,
+            back_to_back: false,
+            perfect_clears: 0,
+            last_perfect_clear: None,
+            seed,
+            rng: ChaCha8Rng::seed_from_u64(seed),
+            garbage_hole: None,
+            garbage_rng: {
+                let mut rng = ChaCha8Rng::seed_from_u64(seed);
+                rng.set_stream(1);
+                rng
+            },
+            held: None,
+            queue: VecDeque::new(),
+            can_hold: true,
+            config,
+            lock_timer: None,
+            lock_resets: 0,
+            last_rotation: None,
+            lowest_row: 0,
+            gravity_progress: 0.0,
+            events: Vec::new(),
+        };
+        top_up_cheese(&mut tmp);
+        let piece = next_piece(&mut tmp);
+        spawn_piece(&mut tmp, piece);
+        tmp.events.clear();
+        tmp
+    }
+
+    /// Applies a player input and returns everything that happened because of it.
+    pub fn apply(&mut self, change: StateChange) -> Vec<Event> {
+        if !self.game_over {
+            self.started.get_or_insert(self.frame);
+            state_change(self, change);
+        }
+        std::mem::take(&mut self.events)
+    }
+
+    /// Advances the game by one [`FRAME`], applying gravity and lock delay.
+    pub fn tick(&mut self) -> Vec<Event> {
+        if !self.game_over {
+            step(self);
+        }
+        std::mem::take(&mut self.events)
+    }
+
+    /// Time since the first input, measured in frames so replays time the same.
+    /// Stops when the game ends. Frontends that time runs to the millisecond keep their
+    /// own clock.
+    pub fn elapsed(&self) -> Duration {
+        let frames = self.started.map_or(0, |started| self.frame - started);
+        // Not `FRAME * frames`, which drifts from the rounding in `FRAME`.
+        Duration::from_nanos(frames * 1_000_000_000 / 60)
+    }
+
+    /// Queues garbage sent by an opponent. Line clears cancel it before it rises.
+    pub fn receive_garbage(&mut self, lines: u32) {
+        self.pending_garbage += lines;
+    }
+
+    /// Lines still to clear before the mode's goal is reached, for modes that have one.
+    pub fn lines_left(&self) -> Option<u32> {
+        let goal = self.config.mode.goal_lines()?;
+        let cleared = match self.config.mode {
+            Mode::Cheese { .. } => self.garbage_cleared,
+            _ => self.lines,
+        };
+        Some(goal.saturating_sub(cleared))
+    }
+
+    /// Time left on the clock, for modes that end on time.
+    pub fn time_left(&self) -> Option<Duration> {
+        let limit = self.config.mode.time_limit()?;
+        Some(limit.saturating_sub(self.elapsed()))
+    }
+
+    /// Current gravity in cells per frame. Never more than the rows on the board, buffer
+    /// included, which is enough to drop a piece from anywhere in one frame.
+    pub fn gravity(&self) -> f32 {
+        let gravity = &self.config.gravity;
+        let index = (self.level.max(1) as usize - 1).min(gravity.len().saturating_sub(1));
+        let gravity = gravity.get(index).copied().unwrap_or(0.0);
+        gravity.min(self.board.num_rows() as f32)
+    }
+
+    /// Columns on the board.
+    pub fn width(&self) -> usize {
+        self.board.num_columns()
+    }
+    /// Rows in the visible field, not counting the buffer.
+    pub fn height(&self) -> usize {
+        self.board.num_rows() - self.buffer()
+    }
+    /// Hidden rows at the top of the board.
+    pub fn buffer(&self) -> usize {
+        self.config.buffer
+    }
+    /// Row the active piece would land on if it were hard dropped.
+    pub fn ghost_location(&self) -> isize {
+        let mut row = self.location.1;
+        while piece_fits(self, self.piece, (self.location.0, row + 1)) {
+            row += 1;
+        }
+        row
+    }
+}
+impl Default for AppState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn state_change(state: &mut AppState, change: StateChange) {
+    match change {
+        StateChange::Rotate(rotation_type) => {
+            if let Some(kick) = rotate_piece(state, rotation_type) {
+                state.last_rotation = Some(kick);
+                reset_lock_delay(state);
+            }
+        }
+        StateChange::HoldPiece => hold_piece(state),
+        StateChange::HardDrop => hard_drop(state),
+        StateChange::SoftDrop => {
+            move_down(state);
+        }
+        StateChange::Move(movement_type) => {
+            if move_piece(state, movement_type) {
+                state.last_rotation = None;
+                reset_lock_delay(state);
+            }
+        }
+    }
+}
+fn step(state: &mut AppState) {
+    state.frame += 1;
+    if state.time_left() == Some(Duration::ZERO) {
+        state.completed = true;
+        state.game_over = true;
+        state.events.push(Event::GameOver);
+        return;
+    }
+    state.gravity_progress += state.gravity();
+    while state.gravity_progress >= 1.0 {
+        state.gravity_progress -= 1.0;
+        if !move_down(state) {
+            state.gravity_progress = 0.0;
+        }
+    }
+    if piece_fits(state, state.piece, (state.location.0, state.location.1 + 1)) {
+        state.lock_timer = None;
+        return;
+    }
+    let elapsed = state.lock_timer.get_or_insert(Duration::ZERO);
+    *elapsed += FRAME;
+    if *elapsed >= state.config.lock_delay {
+        lock_and_spawn(state);
+    }
+}
+fn reset_lock_delay(state: &mut AppState) {
+    if state.lock_timer.is_some() && state.lock_resets < state.config.lock_resets {
+        state.lock_timer = Some(Duration::ZERO);
+        state.lock_resets += 1;
+    }
+}
+fn hard_drop(state: &mut AppState) {
+    while move_down(state) {}
+    lock_and_spawn(state);
+}
+/// Guideline spawn position: centred, rounding left, in the two buffer rows just above
+/// the field.
+fn spawn_location(state: &AppState, piece: Tetromino) -> (isize, isize) {
+    let (top, size) = blocks(state, piece).map_or((0, 0), |blocks| {
+        let top = blocks
+            .rows_iter()
+            .position(|mut row| row.any(|block| *block))
+            .unwrap_or_default();
+        (top, blocks.num_columns())
+    });
+    let col = (state.width() as isize - size as isize) / 2;
+    let (x, y) = state
+        .config
+        .pieces
+        .get(piece.piece)
+        .map_or((0, 0), |def| def.spawn);
+    (col + x, state.buffer() as isize - 2 - top as isize - y)
+}
+/// The blocks of `piece` in its box, or `None` for a piece from another set, like one in
+/// an opponent's snapshot.
+fn blocks(state: &AppState, piece: Tetromino) -> Option<&Array2D<bool>> {
+    state.config.pieces.shape(piece)
+}
+/// Brings `piece` in at the top of the board and drops it one row if nothing is in the
+/// way. Ends the game if it overlaps the stack.
+fn spawn_piece(state: &mut AppState, piece: Piece) {
+    state.piece = Tetromino {
+        piece,
+        rotation: Rotation::default(),
+    };
+    state.location = spawn_location(state, state.piece);
+    state.lock_timer = None;
+    state.lock_resets = 0;
+    state.last_rotation = None;
+    state.gravity_progress = 0.0;
+    state.events.push(Event::Spawned(piece));
+    if !piece_is_legal(state) {
+        state.game_over = true;
+        state.loss = Some(Loss::BlockOut);
+        state.events.push(Event::GameOver);
+        return;
+    }
+    if piece_fits(state, state.piece, (state.location.0, state.location.1 + 1)) {
+        state.location.1 += 1;
+    }
+    state.lowest_row = state.location.1;
+}
+
+fn hold_piece(state: &mut AppState) {
+    if state.can_hold {
+        let held = match state.held.take() {
+            Some(x) => x,
+            None => next_piece(state),
+        };
+        state.held = Some(state.piece.piece);
+        spawn_piece(state, held);
+        state.can_hold = false;
+    }
+}
+fn piece_is_legal(state: &AppState) -> bool {
+    piece_fits(state, state.piece, state.location)
+}
+fn piece_fits(state: &AppState, piece: Tetromino, location: (isize, isize)) -> bool {
+    // A piece from another set fits nowhere, so it can't move or drop
+    let Some(blocks) = blocks(state, piece) else {
+        return false;
+    };
+    blocks
+        .enumerate_row_major()
+        .filter_map(|(el, x)| if *x { Some(el) } else { None })
+        .all(|(row, col)| {
+            let row = row as isize + location.1;
+            let col = col as isize + location.0;
+
+            if col < 0 || row < 0 {
+                return false;
+            }
+            // Off the bottom or sides of the board, or above the buffer
+            state.board.get(row as usize, col as usize) == Some(&None)
+        })
+}
+fn move_piece(state: &mut AppState, movement_type: MovementType) -> bool {
+    match movement_type {
+        MovementType::Left => {
+            state.location.0 -= 1;
+            if !piece_is_legal(state) {
+                state.location.0 += 1;
+                return false;
+            }
+        }
+        MovementType::Right => {
+            state.location.0 += 1;
+            if !piece_is_legal(state) {
+                state.location.0 -= 1;
+                return false;
+            }
+        }
+    }
+    true
+}
+fn rotate_piece(state: &mut AppState, rotation_type: MovementType) -> Option<usize> {
+    let old = state.piece.rotation;
+    let new = match rotation_type {
+        MovementType::Right => match old {
+            Rotation::Up => Rotation::Right,
+            Rotation::Right => Rotation::Down,
+            Rotation::Down => Rotation::Left,
+            Rotation::Left => Rotation::Up,
+        },
+        MovementType::Left => match old {
+            Rotation::Up => Rotation::Left,
+            Rotation::Left => Rotation::Down,
+            Rotation::Down => Rotation::Right,
+            Rotation::Right => Rotation::Up,
+        },
+    };
+    let rotated = Tetromino {
+        piece: state.piece.piece,
+        rotation: new,
+    };
+    // Kick tables use y up, the board uses rows counting down.
+    let (kick, location) = state
+        .config
+        .pieces
+        .kicks(state.piece.piece, old, new)
+        .iter()
+        .map(|(x, y)| (state.location.0 + x, state.location.1 - y))
+        .enumerate()
+        .find(|(_, location)| piece_fits(state, rotated, *location))?;
+    state.piece = rotated;
+    state.location = location;
+    state.events.push(Event::Rotated { kick });
+    Some(kick)
+}
+
+/// Moves the active piece down one row. Returns false if it is resting on something.
+fn move_down(state: &mut AppState) -> bool {
+    state.location.1 += 1;
+    if !piece_is_legal(state) {
+        state.location.1 -= 1;
+        return false;
+    }
+    state.last_rotation = None;
+    if state.location.1 > state.lowest_row {
+        state.lowest_row = state.location.1;
+        state.lock_resets = 0;
+    }
+    true
+}
+fn lock_and_spawn(state: &mut AppState) {
+    if lock_piece(state) {
+        state.events.push(Event::GameOver);
+        return;
+    }
+    let piece = next_piece(state);
+    spawn_piece(state, piece);
+}
+
+fn lock_piece(state: &mut AppState) -> bool {
+    state.can_hold = true;
+    let t_spin = t_spin(state);
+    let location = state.location;
+    let blocks = blocks(state, state.piece)
+        .into_iter()
+        .flat_map(Array2D::enumerate_row_major)
+        .filter_map(|((row, col), x)| {
+            if *x {
+                Some((row as isize + location.1, col as isize + location.0))
+            } else {
+                None
+            }
+        })
+        .collect::<Vec<_>>();
+    if blocks.iter().all(|(row, _)| *row < state.buffer() as isize) {
+        state.game_over = true;
+        state.loss = Some(Loss::LockOut);
+        return true;
+    }
+    for (row, col) in blocks {
+        state
+            .board
+            .set(
+                row as usize,
+                col as usize,
+                Some(Cell::Block(state.piece.piece)),
+            )
+            .expect("Unable to set block");
+    }
+    let mut clear = Clear {
+        lines: clear_lines(state),
+        t_spin,
+        ..Default::default()
+    };
+    if clear.lines > 0 && state.board.elements_row_major_iter().all(Option::is_none) {
+        clear.perfect_clear = true;
+        state.perfect_clears += 1;
+        state.last_perfect_clear = Some(state.frame);
+    }
+    if clear.lines > 0 {
+        let combo = state.combo.map_or(0, |combo| combo + 1);
+        state.combo = Some(combo);
+        clear.combo = combo;
+        clear.back_to_back = state.back_to_back && clear.is_difficult();
+        state.back_to_back = clear.is_difficult();
+    } else {
+        state.combo = None;
+    }
+    if clear.lines > 0 {
+        let attack = clear.attack();
+        let cancelled = attack.min(state.pending_garbage);
+        state.pending_garbage -= cancelled;
+        if attack > cancelled {
+            state.events.push(Event::Attack(attack - cancelled));
+        }
+    } else {
+        for _ in 0..std::mem::take(&mut state.pending_garbage) {
+            add_garbage_row(state);
+        }
+    }
+    state.score += clear.score(state.level);
+    state.lines += clear.lines as u32;
+    state.level = state.config.start_level + state.lines / LINES_PER_LEVEL;
+    state.events.push(Event::Locked(clear));
+    if state.lines_left() == Some(0) {
+        state.completed = true;
+        state.game_over = true;
+    }
+    top_up_cheese(state);
+    state.game_over
+}
+
+/// The 3-corner rule. A T piece whose last move was a rotation is a T-spin if three of
+/// the four corners around its centre are blocked. It's a full T-spin if both corners
+/// on the side it points to are blocked, or if it got there with the last SRS kick.
+fn t_spin(state: &AppState) -> TSpin {
+    let Some(kick) = state.last_rotation else {
+        return TSpin::None;
+    };
+    // Only a 3x3 T has the corners the rule looks at, whatever the set calls T.
+    if state.piece.piece != Piece::T || blocks(state, state.piece).map(Array2D::num_rows) != Some(3)
+    {
+        return TSpin::None;
+    }
+    let (col, row) = state.location;
+    let blocked = |x: isize, y: isize| {
+        let (col, row) = (col + x, row + y);
+        if col < 0 || col >= state.width() as isize || row >= state.board.num_rows() as isize {
+            return true;
+        }
+        row >= 0 && state.board[(row as usize, col as usize)].is_some()
+    };
+    let corners = [(0, 0), (2, 0), (0, 2), (2, 2)]
+        .into_iter()
+        .filter(|(x, y)| blocked(*x, *y))
+        .count();
+    if corners < 3 {
+        return TSpin::None;
+    }
+    let front = match state.piece.rotation {
+        Rotation::Up => [(0, 0), (2, 0)],
+        Rotation::Right => [(2, 0), (2, 2)],
+        Rotation::Down => [(0, 2), (2, 2)],
+        Rotation::Left => [(0, 0), (0, 2)],
+    };
+    if front.iter().all(|(x, y)| blocked(*x, *y)) || kick == 4 {
+        TSpin::Full
+    } else {
+        TSpin::Mini
+    }
+}
+
+fn clear_lines(state: &mut AppState) -> u8 {
+    let mut count = 0;
+    let rows = {
+        let mut tmp = state
+            .board
+            .rows_iter()
+            .filter_map(|x| {
+                if x.clone().all(|y| y.is_some()) {
+                    count += 1;
+                    if x.clone().any(|y| *y == Some(Cell::Garbage)) {
+                        state.garbage_cleared += 1;
+                    }
+                    None
+                } else {
+                    Some(x.copied().collect::<Vec<_>>())
+                }
+            })
+            .collect::<Vec<_>>();
+        while tmp.len() < state.board.num_rows() {
+            tmp.insert(0, vec![None; state.width()])
+        }
+        tmp
+    };
+    state.board = Array2D::from_rows(&rows).unwrap();
+    count
+}
+/// Adds garbage rows in cheese mode until there are `cheese_height` on the board, or as
+/// many as are left to dig.
+fn top_up_cheese(state: &mut AppState) {
+    let Mode::Cheese { lines } = state.config.mode else {
+        return;
+    };
+    let visible = state
+        .board
+        .rows_iter()
+        .filter(|row| row.clone().any(|cell| *cell == Some(Cell::Garbage)))
+        .count() as u32;
+    let wanted = (state.config.cheese_height as u32).min(lines - state.garbage_cleared.min(lines));
+    for _ in visible..wanted {
+        add_garbage_row(state);
+    }
+}
+/// Pushes the stack up by one row and fills the bottom row with garbage, leaving one
+/// hole. Ends the game if the stack is pushed out of the top of the buffer.
+fn add_garbage_row(state: &mut AppState) {
+    let hole = match state.garbage_hole {
+        Some(hole)
+            if !state
+                .garbage_rng
+                .gen_bool(state.config.hole_change.clamp(0.0, 1.0)) =>
+        {
+            hole
+        }
+        Some(hole) => {
+            let new = state.garbage_rng.gen_range(0..state.width() - 1);
+            if new >= hole {
+                new + 1
+            } else {
+                new
+            }
+        }
+        None => state.garbage_rng.gen_range(0..state.width()),
+    };
+    state.garbage_hole = Some(hole);
+
+    let mut rows = state.board.as_rows();
+    let top = rows.remove(0);
+    if top.iter().any(Option::is_some) {
+        state.game_over = true;
+        state.loss = Some(Loss::TopOut);
+    }
+    rows.push(
+        (0..state.width())
+            .map(|col| (col != hole).then_some(Cell::Garbage))
+            .collect(),
+    );
+    state.board = Array2D::from_rows(&rows).unwrap();
+}
+fn next_piece(state: &mut AppState) -> Piece {
+    while state.queue.len() <= state.config.preview {
+        let piece = state.randomizer.next(&mut state.rng);
+        state.queue.push_back(piece);
+    }
+    state.queue.pop_front().expect("Queue was just filled")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A game on an empty board, with `piece` about to be placed at `location`.
+    fn game_with(config: Config, piece: Piece, location: (isize, isize)) -> AppState {
+        let mut state = AppState::from_seed(config, 0);
+        state.piece = Tetromino {
+            piece,
+            rotation: Rotation::Up,
+        };
+        state.location = location;
+        state
+    }
+
+    /// Fills the bottom `rows` rows, leaving `hole` empty.
+    fn fill_bottom(state: &mut AppState, rows: usize, hole: usize) {
+        let total = state.board.num_rows();
+        for row in total - rows..total {
+            for col in (0..state.width()).filter(|col| *col != hole) {
+                state.board[(row, col)] = Some(Cell::Block(Piece::O));
+            }
+        }
+    }
+
+    fn locked(events: &[Event]) -> Option<Clear> {
+        events.iter().find_map(|event| match event {
+            Event::Locked(clear) => Some(*clear),
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn pentominoes_can_clear_five_lines() {
+        let bar = r#"["..#..", "..#..", "..#..", "..#..", "..#.."]"#;
+        let pieces = format!(
+            "[kicks]\nnone = [{none}]\n[[piece]]\nname = \"I\"\ncolor = [0, 0, 0]\n\
+             kicks = \"none\"\nshapes = [{bar}, {bar}, {bar}, {bar}]\n",
+            none = ["[[0, 0]]"; 8].join(", "),
+        );
+        let config = Config {
+            pieces: Arc::new(pieces.parse().unwrap()),
+            ..Default::default()
+        };
+        let mut state = game_with(config, Piece::I, (-2, 0));
+        fill_bottom(&mut state, 5, 0);
+        let clear = locked(&state.apply(StateChange::HardDrop)).unwrap();
+        assert_eq!(clear.lines, 5);
+        assert!(clear.perfect_clear);
+        assert_eq!(state.score, 1200 + 2000);
+        assert_eq!(state.lines, 5);
+    }
+
+    #[test]
+    fn pieces_from_another_set_do_not_panic() {
+        let mut state = game_with(Config::default(), Piece::from_char('P').unwrap(), (3, 18));
+        assert_eq!(state.ghost_location(), 18);
+        state.apply(StateChange::Move(MovementType::Left));
+        state.apply(StateChange::Rotate(MovementType::Right));
+        state.apply(StateChange::HardDrop);
+        assert_eq!(state.loss, Some(Loss::LockOut));
+    }
+
+    #[test]
+    fn rotations_kick_off_walls() {
+        let mut state = game_with(Config::default(), Piece::T, (3, 30));
+        let events = state.apply(StateChange::Rotate(MovementType::Right));
+        assert_eq!(events, [Event::Rotated { kick: 0 }]);
+        assert_eq!(state.location, (3, 30));
+
+        // Pointing right against the left wall, the box sticks out a column. Turning to
+        // point down needs that column, so SRS kicks the piece one to the right.
+        state.location = (-1, 30);
+        let events = state.apply(StateChange::Rotate(MovementType::Right));
+        assert_eq!(events, [Event::Rotated { kick: 1 }]);
+        assert_eq!(state.piece.rotation, Rotation::Down);
+        assert_eq!(state.location, (0, 30));
+    }
+
+    #[test]
+    fn rotations_with_no_room_fail() {
+        // An I lying in a tunnel one row high has nowhere to stand up
+        let mut state = game_with(Config::default(), Piece::I, (3, 34));
+        for row in (30..40).filter(|row| *row != 35) {
+            for col in 0..state.width() {
+                state.board[(row, col)] = Some(Cell::Block(Piece::O));
+            }
+        }
+        assert!(state
+            .apply(StateChange::Rotate(MovementType::Left))
+            .is_empty());
+        assert!(state
+            .apply(StateChange::Rotate(MovementType::Right))
+            .is_empty());
+        assert_eq!(state.piece.rotation, Rotation::Up);
+        assert_eq!(state.location, (3, 34));
+    }
+
+    /// Ticks until the active piece locks, or `None` if it hasn't after a thousand.
+    /// With `moves_every`, the piece steps left and right in turn every that many ticks.
+    fn ticks_to_lock(state: &mut AppState, moves_every: Option<u32>) -> Option<u32> {
+        (1..=1000).find(|tick| {
+            if let Some(every) = moves_every.filter(|every| tick % every == 0) {
+                let direction = if tick / every % 2 == 1 {
+                    MovementType::Left
+                } else {
+                    MovementType::Right
+                };
+                state.apply(StateChange::Move(direction));
+            }
+            locked(&state.tick()).is_some()
+        })
+    }
+
+    /// The lock delay in whole ticks.
+    fn lock_delay_ticks(config: &Config) -> u32 {
+        config.lock_delay.as_nanos().div_ceil(FRAME.as_nanos()) as u32
+    }
+
+    #[test]
+    fn pieces_lock_after_the_lock_delay() {
+        let mut state = game_with(Config::default(), Piece::O, (3, 38));
+        let delay = lock_delay_ticks(&state.config);
+        assert_eq!(ticks_to_lock(&mut state, None), Some(delay));
+    }
+
+    #[test]
+    fn moves_reset_the_lock_delay_a_limited_number_of_times() {
+        let mut state = game_with(Config::default(), Piece::O, (3, 38));
+        let delay = lock_delay_ticks(&state.config);
+        // Moving just before the piece would lock keeps it alive until the resets run
+        // out, then it locks a full delay after the last reset. The tick the last reset
+        // happens on counts towards that delay
+        let every = delay - 1;
+        let ticks = ticks_to_lock(&mut state, Some(every));
+        assert_eq!(ticks, Some(state.config.lock_resets * every + delay - 1));
+    }
+
+    #[test]
+    fn falling_further_earns_the_resets_back() {
+        let mut state = game_with(Config::default(), Piece::O, (3, 30));
+        let delay = lock_delay_ticks(&state.config);
+        state.lock_timer = Some(Duration::ZERO);
+        state.lock_resets = state.config.lock_resets;
+        // Out of resets, but dropping to a new lowest row hands them all back
+        state.apply(StateChange::SoftDrop);
+        assert_eq!(state.lock_resets, 0);
+        state.location.1 = state.ghost_location();
+        let every = delay - 1;
+        let ticks = ticks_to_lock(&mut state, Some(every));
+        assert_eq!(ticks, Some(state.config.lock_resets * every + delay - 1));
+    }
+
+    /// Fills `row`, leaving the columns in `holes` empty.
+    fn fill_row(state: &mut AppState, row: usize, holes: &[usize]) {
+        for col in (0..state.width()).filter(|col| !holes.contains(col)) {
+            state.board[(row, col)] = Some(Cell::Block(Piece::O));
+        }
+    }
+
+    /// A T pointing left beside a T-slot in the bottom two rows, with or without the
+    /// block over the slot that makes the third corner. A block off to the side keeps
+    /// the board from clearing.
+    fn t_slot(overhang: bool) -> AppState {
+        let mut state = game_with(Config::default(), Piece::T, (3, 37));
+        state.piece.rotation = Rotation::Left;
+        state.board[(37, 9)] = Some(Cell::Block(Piece::O));
+        fill_row(&mut state, 38, &[3, 4, 5]);
+        fill_row(&mut state, 39, &[4]);
+        if overhang {
+            state.board[(37, 3)] = Some(Cell::Block(Piece::O));
+        }
+        state
+    }
+
+    #[test]
+    fn t_spins_need_three_corners() {
+        let mut state = t_slot(true);
+        let events = state.apply(StateChange::Rotate(MovementType::Left));
+        assert_eq!(events, [Event::Rotated { kick: 0 }]);
+        let events = state.apply(StateChange::HardDrop);
+        let clear = locked(&events).unwrap();
+        assert_eq!((clear.lines, clear.t_spin), (2, TSpin::Full));
+        assert!(events.contains(&Event::Attack(4)));
+        assert_eq!(state.score, 1200);
+
+        let mut state = t_slot(false);
+        state.apply(StateChange::Rotate(MovementType::Left));
+        let clear = locked(&state.apply(StateChange::HardDrop)).unwrap();
+        assert_eq!((clear.lines, clear.t_spin), (2, TSpin::None));
+        assert_eq!(state.score, 300);
+    }
+
+    #[test]
+    fn t_spins_with_an_open_front_corner_are_mini() {
+        let mut state = game_with(Config::default(), Piece::T, (3, 37));
+        state.piece.rotation = Rotation::Left;
+        state.board[(37, 3)] = Some(Cell::Block(Piece::O));
+        state.board[(37, 5)] = Some(Cell::Block(Piece::O));
+        fill_row(&mut state, 38, &[3, 4, 5]);
+        fill_row(&mut state, 39, &[4, 5]);
+        state.apply(StateChange::Rotate(MovementType::Left));
+        let clear = locked(&state.apply(StateChange::HardDrop)).unwrap();
+        assert_eq!((clear.lines, clear.t_spin), (1, TSpin::Mini));
+        assert_eq!(state.score, 200);
+    }
+
+    #[test]
+    fn the_last_kick_makes_a_mini_t_spin_full() {
+        // Every kick but the last, one left and two down, is blocked. The T ends up
+        // pointing right with only one of its front corners blocked
+        let mut state = game_with(Config::default(), Piece::T, (4, 35));
+        for cell in [(35, 4), (37, 5), (37, 3), (39, 3)] {
+            state.board[cell] = Some(Cell::Block(Piece::O));
+        }
+        let events = state.apply(StateChange::Rotate(MovementType::Right));
+        assert_eq!(events, [Event::Rotated { kick: 4 }]);
+        assert_eq!(state.location, (3, 37));
+        let clear = locked(&state.apply(StateChange::HardDrop)).unwrap();
+        assert_eq!((clear.lines, clear.t_spin), (0, TSpin::Full));
+        assert_eq!(state.score, 400);
+    }
+
+    #[test]
+    fn t_spins_need_a_rotation_last() {
+        // The T spins in the air, then drops and slides under the overhang
+        let mut state = game_with(Config::default(), Piece::T, (4, 36));
+        state.board[(37, 3)] = Some(Cell::Block(Piece::O));
+        fill_row(&mut state, 39, &[9]);
+        state.apply(StateChange::Rotate(MovementType::Right));
+        state.apply(StateChange::Rotate(MovementType::Left));
+        state.apply(StateChange::SoftDrop);
+        state.apply(StateChange::Move(MovementType::Left));
+        assert_eq!(state.location, (3, 37));
+        let clear = locked(&state.apply(StateChange::HardDrop)).unwrap();
+        assert_eq!(clear.t_spin, TSpin::None);
+        assert_eq!(state.score, 0);
+    }
+
+    #[test]
+    fn clearing_the_board_scores_a_perfect_clear() {
+        let mut state = game_with(Config::default(), Piece::I, (0, 38));
+        fill_row(&mut state, 39, &[0, 1, 2, 3]);
+        for _ in 0..5 {
+            state.tick();
+        }
+        let events = state.apply(StateChange::HardDrop);
+        let clear = locked(&events).unwrap();
+        assert_eq!(clear.lines, 1);
+        assert!(clear.perfect_clear);
+        assert!(events.contains(&Event::Attack(10)));
+        assert_eq!(state.score, 100 + 800);
+        assert_eq!(state.perfect_clears, 1);
+        assert_eq!(state.last_perfect_clear, Some(state.frame));
+
+        // A block left over above the cleared line is no perfect clear
+        let mut state = game_with(Config::default(), Piece::I, (0, 38));
+        fill_row(&mut state, 39, &[0, 1, 2, 3]);
+        state.board[(38, 9)] = Some(Cell::Block(Piece::O));
+        let clear = locked(&state.apply(StateChange::HardDrop)).unwrap();
+        assert_eq!(clear.lines, 1);
+        assert!(!clear.perfect_clear);
+        assert_eq!(state.score, 100);
+        assert_eq!(state.perfect_clears, 0);
+    }
+
+    #[test]
+    fn top_gravity_drops_pieces_on_tall_boards_in_one_frame() {
+        let config = Config {
+            height: 40,
+            start_level: 20,
+            gravity: vec![f32::INFINITY],
+            ..Default::default()
+        };
+        let mut state = AppState::from_seed(config, 0);
+        assert_eq!(state.gravity(), state.board.num_rows() as f32);
+        state.tick();
+        assert_eq!(state.location.1, state.ghost_location());
+
+        let guideline = AppState::from_seed(
+            Config {
+                height: 40,
+                start_level: 20,
+                ..Default::default()
+            },
+            0,
+        );
+        assert!(guideline.gravity() > ROWS as f32);
+    }
+
+    /// The board cells the active piece covers, as `(row, col)`.
+    fn piece_cells(state: &AppState) -> Vec<(isize, isize)> {
+        let (col, row) = state.location;
+        blocks(state, state.piece)
+            .unwrap()
+            .enumerate_row_major()
+            .filter(|(_, block)| **block)
+            .map(|((r, c), _)| (row + r as isize, col + c as isize))
+            .collect()
+    }
+
+    #[test]
+    fn pieces_spawn_at_the_guideline_position() {
+        // Rows 18 and 19 are the two buffer rows above the 20 row field. Pieces spawn
+        // with their top in row 18, centred and rounding left, then drop a row straight
+        // away if they can
+        let cases = [
+            (Piece::I, vec![(18, 3), (18, 4), (18, 5), (18, 6)]),
+            (Piece::O, vec![(18, 4), (18, 5), (19, 4), (19, 5)]),
+            (Piece::T, vec![(18, 4), (19, 3), (19, 4), (19, 5)]),
+        ];
+        for (piece, spawned) in cases {
+            let mut state = AppState::from_seed(Config::default(), 0);
+            assert_eq!(state.width(), 10);
+            spawn_piece(&mut state, piece);
+            let dropped = spawned
+                .iter()
+                .map(|(row, col)| (row + 1, *col))
+                .collect::<Vec<_>>();
+            assert_eq!(piece_cells(&state), dropped, "{piece:?}");
+
+            // With the row below it taken, the piece stays where it spawned
+            let mut state = AppState::from_seed(Config::default(), 0);
+            let below = spawned.iter().map(|(row, _)| row + 1).max().unwrap();
+            fill_row(&mut state, below as usize, &[]);
+            spawn_piece(&mut state, piece);
+            assert_eq!(piece_cells(&state), spawned, "{piece:?}");
+            assert_eq!(state.loss, None);
+        }
+    }
+
+    #[test]
+    fn spawning_into_the_stack_is_a_block_out() {
+        let mut state = AppState::from_seed(Config::default(), 0);
+        state.board[(19, 4)] = Some(Cell::Garbage);
+        spawn_piece(&mut state, Piece::T);
+        assert!(state.game_over);
+        assert_eq!(state.loss, Some(Loss::BlockOut));
+        assert!(state.events.contains(&Event::GameOver));
+    }
+
+    #[test]
+    fn locking_entirely_in_the_buffer_is_a_lock_out() {
+        let mut state = game_with(Config::default(), Piece::O, (4, 10));
+        state.board[(12, 4)] = Some(Cell::Garbage);
+        let events = state.apply(StateChange::HardDrop);
+        assert!(events.contains(&Event::GameOver));
+        assert_eq!(state.loss, Some(Loss::LockOut));
+
+        // Reaching into the field by one row is fine
+        let mut state = game_with(Config::default(), Piece::O, (0, 18));
+        state.board[(21, 0)] = Some(Cell::Garbage);
+        state.apply(StateChange::HardDrop);
+        assert_eq!(state.loss, None);
+    }
+
+    #[test]
+    fn garbage_pushing_the_stack_out_of_the_buffer_is_a_top_out() {
+        let mut state = game_with(Config::default(), Piece::O, (0, 0));
+        state.location.1 = state.ghost_location();
+        state.board[(0, 9)] = Some(Cell::Garbage);
+        state.receive_garbage(1);
+        let events = state.apply(StateChange::HardDrop);
+        assert!(events.contains(&Event::GameOver));
+        assert_eq!(state.loss, Some(Loss::TopOut));
+
+        // The same garbage under a buffer with room to spare is no loss
+        let mut state = game_with(Config::default(), Piece::O, (0, 0));
+        state.location.1 = state.ghost_location();
+        state.board[(1, 9)] = Some(Cell::Garbage);
+        state.receive_garbage(1);
+        state.apply(StateChange::HardDrop);
+        assert_eq!(state.loss, None);
+        assert_eq!(state.board[(0, 9)], Some(Cell::Garbage));
+    }
+
+    fn cheese(lines: u32, cheese_height: usize, hole_change: f64) -> Config {
+        Config {
+            mode: Mode::Cheese { lines },
+            cheese_height,
+            hole_change,
+            ..Default::default()
+        }
+    }
+
+    /// The hole in each row with garbage in it, from the top down. Every garbage row
+    /// must have exactly one.
+    fn garbage_holes(state: &AppState) -> Vec<usize> {
+        state
+            .board
+            .rows_iter()
+            .filter(|row| row.clone().any(|cell| *cell == Some(Cell::Garbage)))
+            .map(|row| {
+                let holes = row
+                    .enumerate()
+                    .filter(|(_, cell)| cell.is_none())
+                    .map(|(col, _)| col)
+                    .collect::<Vec<_>>();
+                assert_eq!(holes.len(), 1, "{holes:?}");
+                holes[0]
+            })
+            .collect()
+    }
+
+    /// Drops a vertical I into `col`.
+    fn drop_i_into(state: &mut AppState, col: usize) -> Vec<Event> {
+        state.piece = Tetromino {
+            piece: Piece::I,
+            rotation: Rotation::Right,
+        };
+        state.location = (col as isize - 2, 0);
+        state.apply(StateChange::HardDrop)
+    }
+
+    #[test]
+    fn cheese_rows_have_one_hole_each() {
+        for seed in 0..20 {
+            let state = AppState::from_seed(cheese(100, 10, 0.5), seed);
+            assert_eq!(garbage_holes(&state).len(), 10);
+        }
+    }
+
+    #[test]
+    fn hole_change_decides_whether_holes_line_up() {
+        for seed in 0..20 {
+            let state = AppState::from_seed(cheese(100, 10, 0.0), seed);
+            let holes = garbage_holes(&state);
+            assert!(holes.windows(2).all(|pair| pair[0] == pair[1]), "{holes:?}");
+
+            let state = AppState::from_seed(cheese(100, 10, 1.0), seed);
+            let holes = garbage_holes(&state);
+            assert!(holes.windows(2).all(|pair| pair[0] != pair[1]), "{holes:?}");
+        }
+    }
+
+    #[test]
+    fn cheese_is_topped_up_to_its_height_but_not_past_the_lines_left() {
+        let state = AppState::from_seed(cheese(100, 4, 0.3), 0);
+        assert_eq!(garbage_holes(&state).len(), 4);
+        let state = AppState::from_seed(cheese(3, 10, 0.3), 0);
+        assert_eq!(garbage_holes(&state).len(), 3);
+
+        // Clearing a garbage row brings another one up
+        let mut state = AppState::from_seed(cheese(100, 4, 1.0), 0);
+        let hole = garbage_holes(&state)[0];
+        let clear = locked(&drop_i_into(&mut state, hole)).unwrap();
+        assert_eq!(clear.lines, 1);
+        assert_eq!(garbage_holes(&state).len(), 4);
+        assert_eq!(state.garbage_cleared, 1);
+        assert_eq!(state.lines_left(), Some(99));
+    }
+
+    #[test]
+    fn digging_out_the_last_garbage_row_finishes_cheese() {
+        let mut state = AppState::from_seed(cheese(2, 10, 1.0), 0);
+        let hole = garbage_holes(&state)[0];
+        drop_i_into(&mut state, hole);
+        assert_eq!(state.lines_left(), Some(1));
+        assert!(!state.game_over);
+        // With one line left to dig, no more garbage comes up
+        let holes = garbage_holes(&state);
+        assert_eq!(holes.len(), 1);
+
+        let events = drop_i_into(&mut state, holes[0]);
+        assert_eq!(state.garbage_cleared, 2);
+        assert_eq!(state.lines_left(), Some(0));
+        assert!(state.completed);
+        assert!(state.game_over);
+        assert!(events.contains(&Event::GameOver));
+        assert_eq!(state.loss, None);
+    }
+
+    #[test]
+    fn gravity_builds_up_over_frames() {
+        let config = Config {
+            gravity: vec![0.25],
+            ..Default::default()
+        };
+        let mut state = game_with(config, Piece::O, (4, 0));
+        for _ in 0..3 {
+            state.tick();
+        }
+        assert_eq!(state.location.1, 0);
+        state.tick();
+        assert_eq!(state.location.1, 1);
+        for _ in 0..4 {
+            state.tick();
+        }
+        assert_eq!(state.location.1, 2);
+
+        // Whole cells fall at once and the rest carries over
+        let config = Config {
+            gravity: vec![2.5],
+            ..Default::default()
+        };
+        let mut state = game_with(config, Piece::O, (4, 0));
+        state.tick();
+        assert_eq!(state.location.1, 2);
+        state.tick();
+        assert_eq!(state.location.1, 5);
+    }
+
+    #[test]
+    fn the_level_goes_up_every_ten_lines() {
+        let config = Config {
+            gravity: vec![0.1, 0.2, 0.3],
+            ..Default::default()
+        };
+        let clear_a_line = |state: &mut AppState| {
+            state.piece = Tetromino {
+                piece: Piece::I,
+                rotation: Rotation::Up,
+            };
+            state.location = (0, 30);
+            fill_row(state, 39, &[0, 1, 2, 3]);
+            assert_eq!(
+                locked(&state.apply(StateChange::HardDrop)).unwrap().lines,
+                1
+            );
+        };
+        let mut state = AppState::from_seed(config.clone(), 0);
+        for _ in 0..LINES_PER_LEVEL - 1 {
+            clear_a_line(&mut state);
+        }
+        assert_eq!(state.level, 1);
+        assert_eq!(state.gravity(), 0.1);
+        clear_a_line(&mut state);
+        assert_eq!(state.level, 2);
+        assert_eq!(state.gravity(), 0.2);
+
+        // Levels count up from the starting level, and past the table keep its last speed
+        let mut state = AppState::from_seed(
+            Config {
+                start_level: 3,
+                ..config
+            },
+            0,
+        );
+        assert_eq!(state.gravity(), 0.3);
+        for _ in 0..LINES_PER_LEVEL {
+            clear_a_line(&mut state);
+        }
+        assert_eq!(state.level, 4);
+        assert_eq!(state.gravity(), 0.3);
+    }
+
+    #[test]
+    fn races_keep_the_first_level_speed() {
+        for mode in [Mode::Sprint { lines: 40 }, Mode::Cheese { lines: 18 }] {
+            let mut state = AppState::from_seed(
+                Config {
+                    mode,
+                    gravity: mode.gravity(),
+                    ..Default::default()
+                },
+                0,
+            );
+            state.level = 15;
+            assert_eq!(state.gravity(), guideline_gravity()[0], "{mode}");
+        }
+        let mut state = AppState::from_seed(Config::default(), 0);
+        state.level = 15;
+        assert_eq!(state.gravity(), guideline_gravity()[14]);
+    }
+
+    #[test]
+    fn the_same_seed_and_inputs_play_the_same_game() {
+        // The bot writes the script, with a few gravity ticks between its pieces
+        let mut first = AppState::from_seed(Config::default(), 42);
+        let mut script = Vec::new();
+        for piece in 0..40 {
+            let placement = crate::Bot::default().best(&first).unwrap();
+            for change in placement.inputs {
+                first.apply(change);
+                script.push(Some(change));
+            }
+            for _ in 0..piece % 5 {
+                first.tick();
+                script.push(None);
+            }
+        }
+        assert!(first.lines > 0);
+
+        let mut second = AppState::from_seed(Config::default(), 42);
+        for step in script {
+            match step {
+                Some(change) => second.apply(change),
+                None => second.tick(),
+            };
+        }
+        assert_eq!(second.queue, first.queue);
+        assert_eq!(second.board, first.board);
+        assert_eq!(second.score, first.score);
+
+        assert_ne!(
+            AppState::from_seed(Config::default(), 43).queue,
+            AppState::from_seed(Config::default(), 42).queue
+        );
+    }
+}
@@ -0,0 +1,266 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tetris::{AppState, Config, Piece, PieceSet, COLS, ROWS};
+use thiserror::Error;
+
+/// Entries kept per mode and board size.
+pub(crate) const TABLE_SIZE: usize = 10;
+
+#[derive(Debug, Error)]
+pub(crate) enum HighScoreError {
+    #[error("could not access {}: {source}", path.display())]
+    Io { path: PathBuf, source: io::Error },
+    #[error("could not parse {}: {source}", path.display())]
+    Parse {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+    #[error("could not write high scores: {0}")]
+    Serialize(#[from] toml::ser::Error),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct HighScore {
+    pub(crate) name: String,
+    pub(crate) score: u64,
+    pub(crate) lines: u32,
+    pub(crate) level: u32,
+    pub(crate) duration_ms: u64,
+    pub(crate) seed: u64,
+    /// When the game ended, in seconds since the Unix epoch.
+    pub(crate) date: u64,
+}
+impl HighScore {
+    /// An entry for the finished game in `state`, which took `time` of real time.
+    pub(crate) fn new(name: &str, state: &AppState, time: Duration) -> Self {
+        HighScore {
+            name: name.to_string(),
+            score: state.score,
+            lines: state.lines,
+            level: state.level,
+            duration_ms: time.as_millis() as u64,
+            seed: state.seed,
+            date: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+        }
+    }
+    pub(crate) fn duration(&self) -> Duration {
+        Duration::from_millis(self.duration_ms)
+    }
+    /// The date as `YYYY-MM-DD` in UTC.
+    pub(crate) fn date_string(&self) -> String {
+        // Howard Hinnant's days-to-civil algorithm
+        let days = (self.date / 86400) as i64 + 719468;
+        let era = days.div_euclid(146097);
+        let day_of_era = days.rem_euclid(146097);
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let month_index = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+        let month = if month_index < 10 {
+            month_index + 3
+        } else {
+            month_index - 9
+        };
+        let year = year_of_era + era * 400 + i64::from(month <= 2);
+        format!("{year:04}-{month:02}-{day:02}")
+    }
+}
+
+/// The best games per mode and board size, kept on disk between runs.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct HighScores {
+    path: Option<PathBuf>,
+    modes: BTreeMap<String, Vec<HighScore>>,
+}
+impl HighScores {
+    /// Reads `highscores.toml` from the user's data directory. A missing file is an empty table.
+    pub(crate) fn load() -> Result<Self, HighScoreError> {
+        let Some(path) = dirs::data_dir().map(|dir| dir.join("tetris").join("highscores.toml"))
+        else {
+            return Ok(Self::default());
+        };
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(source) => return Err(HighScoreError::Io { path, source }),
+        };
+        let modes = match toml::from_str(&text) {
+            Ok(modes) => modes,
+            Err(source) => return Err(HighScoreError::Parse { path, source }),
+        };
+        Ok(HighScores {
+            path: Some(path),
+            modes,
+        })
+    }
+    pub(crate) fn save(&self) -> Result<(), HighScoreError> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let text = toml::to_string(&self.modes)?;
+        let io_error = |source| HighScoreError::Io {
+            path: path.clone(),
+            source,
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(io_error)?;
+        }
+        fs::write(path, text).map_err(io_error)
+    }
+    pub(crate) fn table(&self, config: &Config) -> &[HighScore] {
+        self.modes
+            .get(&table_name(config))
+            .map_or(&[], Vec::as_slice)
+    }
+    /// Adds `entry` to the table for games played with `config`. Returns its rank if it
+    /// made the table. Timed modes rank the fastest games first, the rest the highest
+    /// scores.
+    pub(crate) fn insert(&mut self, config: &Config, entry: HighScore) -> Option<usize> {
+        let mode = config.mode;
+        let table = self.modes.entry(table_name(config)).or_default();
+        let beats = |other: &HighScore| {
+            if mode.ranked_by_time() {
+                entry.duration_ms < other.duration_ms
+            } else {
+                entry.score > other.score
+            }
+        };
+        let rank = table.iter().position(beats).unwrap_or(table.len());
+        if rank >= TABLE_SIZE {
+            return None;
+        }
+        table.insert(rank, entry);
+        table.truncate(TABLE_SIZE);
+        Some(rank)
+    }
+}
+
+/// The mode, plus the board size, pieces and randomizer if they aren't the usual ones,
+/// so games with different rules aren't ranked against each other.
+fn table_name(config: &Config) -> String {
+    let mut name = config.mode.to_string();
+    if (config.width, config.height) != (COLS, ROWS) {
+        name += &format!("-{}x{}", config.width, config.height);
+    }
+    if *config.pieces != *PieceSet::tetrominoes() {
+        name.push('-');
+        name.extend(config.pieces.pieces().into_iter().map(Piece::to_char));
+    }
+    if config.randomizer != config.mode.randomizer() {
+        name += &format!("-{}", config.randomizer);
+    }
+    name
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tetris::Mode;
+
+    fn entry(name: &str, score: u64, duration_ms: u64) -> HighScore {
+        HighScore {
+            name: name.to_string(),
+            score,
+            lines: 0,
+            level: 1,
+            duration_ms,
+            seed: 0,
+            date: 0,
+        }
+    }
+
+    fn names(table: &[HighScore]) -> Vec<&str> {
+        table.iter().map(|entry| entry.name.as_str()).collect()
+    }
+
+    #[test]
+    fn scores_rank_highest_first_and_times_fastest_first() {
+        let marathon = Config::default();
+        let sprint = Config {
+            mode: Mode::Sprint { lines: 40 },
+            ..Default::default()
+        };
+        // The higher scores took longer, so the two orders disagree
+        let entries = [
+            entry("a", 300, 9000),
+            entry("b", 100, 1000),
+            entry("c", 200, 5000),
+        ];
+        let mut scores = HighScores::default();
+        let ranks = entries
+            .iter()
+            .map(|entry| scores.insert(&marathon, entry.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(ranks, [Some(0), Some(1), Some(1)]);
+        assert_eq!(names(scores.table(&marathon)), ["a", "c", "b"]);
+        let ranks = entries
+            .iter()
+            .map(|entry| scores.insert(&sprint, entry.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(ranks, [Some(0), Some(0), Some(1)]);
+        assert_eq!(names(scores.table(&sprint)), ["b", "c", "a"]);
+    }
+
+    #[test]
+    fn ties_go_below_the_games_already_on_the_table() {
+        let config = Config::default();
+        let mut scores = HighScores::default();
+        scores.insert(&config, entry("first", 100, 0));
+        assert_eq!(scores.insert(&config, entry("second", 100, 0)), Some(1));
+        assert_eq!(names(scores.table(&config)), ["first", "second"]);
+    }
+
+    #[test]
+    fn the_table_keeps_only_the_best_games() {
+        let config = Config::default();
+        let mut scores = HighScores::default();
+        for score in 1..=TABLE_SIZE as u64 {
+            scores.insert(&config, entry("old", score * 10, 0));
+        }
+        assert_eq!(scores.insert(&config, entry("low", 5, 0)), None);
+        assert_eq!(scores.insert(&config, entry("tied", 10, 0)), None);
+        assert_eq!(
+            scores.insert(&config, entry("new", 15, 0)),
+            Some(TABLE_SIZE - 1)
+        );
+        let table = scores.table(&config);
+        assert_eq!(table.len(), TABLE_SIZE);
+        assert_eq!(table.last().unwrap().name, "new");
+        assert!(table.iter().all(|entry| entry.score > 10));
+    }
+
+    #[test]
+    fn dates_are_shown_as_utc_calendar_dates() {
+        let cases = [
+            (0, "1970-01-01"),
+            (86399, "1970-01-01"),
+            (1703980800, "2023-12-31"),
+            (1704067200, "2024-01-01"),
+            // Leap days, either side of the March start of the algorithm's year
+            (1709164800, "2024-02-29"),
+            (1709251200, "2024-03-01"),
+            (951782400, "2000-02-29"),
+            (951868800, "2000-03-01"),
+            // 2100 is not a leap year
+            (4107456000, "2100-02-28"),
+            (4107542400, "2100-03-01"),
+        ];
+        for (date, expected) in cases {
+            let score = HighScore {
+                date,
+                ..entry("", 0, 0)
+            };
+            assert_eq!(score.date_string(), expected, "{date}");
+        }
+    }
+}
@@ -0,0 +1,243 @@
+use std::time::Duration;
+
+use crate::game::{MovementType, StateChange, FRAME};
+
+/// How held keys repeat. Every timing is measured on the game clock, not the
+/// operating system's key repeat.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Handling {
+    /// Delayed auto shift: how long a direction has to be held before it repeats.
+    pub das: Duration,
+    /// Auto repeat rate: time between repeated moves once DAS has charged.
+    /// Zero moves the piece straight to the wall.
+    pub arr: Duration,
+    /// Time between soft drop steps while soft drop is held.
+    /// Zero drops the piece straight to the floor.
+    pub soft_drop: Duration,
+}
+impl Default for Handling {
+    fn default() -> Self {
+        Handling {
+            das: Duration::from_millis(167),
+            arr: Duration::from_millis(33),
+            soft_drop: Duration::from_millis(33),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+struct Held {
+    held_for: Duration,
+    repeats: u32,
+}
+
+/// Tracks which repeatable inputs are held and turns them into [`StateChange`]s.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Controls {
+    pub handling: Handling,
+    left: Option<Held>,
+    right: Option<Held>,
+    soft_drop: Option<Held>,
+    /// The most recently pressed direction, which wins while both are held.
+    direction: Option<MovementType>,
+}
+impl Controls {
+    pub fn new(handling: Handling) -> Self {
+        Controls {
+            handling,
+            ..Default::default()
+        }
+    }
+
+    /// A key for `change` went down. Returns the changes to apply right away.
+    /// Presses of a key that is already held are ignored, so OS key repeat does nothing.
+    pub fn press(&mut self, change: StateChange) -> Vec<StateChange> {
+        match change {
+            StateChange::Move(direction) => {
+                let held = self.held_mut(direction);
+                if held.is_some() {
+                    return Vec::new();
+                }
+                *held = Some(Held::default());
+                self.direction = Some(direction);
+                vec![change]
+            }
+            StateChange::SoftDrop => {
+                if self.soft_drop.is_some() {
+                    return Vec::new();
+                }
+                self.soft_drop = Some(Held::default());
+                vec![change]
+            }
+            _ => vec![change],
+        }
+    }
+
+    /// A key for `change` went up.
+    pub fn release(&mut self, change: StateChange) {
+        match change {
+            StateChange::Move(direction) => {
+                *self.held_mut(direction) = None;
+                if self.direction == Some(direction) {
+                    let other = match direction {
+                        MovementType::Left => MovementType::Right,
+                        MovementType::Right => MovementType::Left,
+                    };
+                    self.direction = self.held_mut(other).as_mut().map(|held| {
+                        *held = Held::default();
+                        other
+                    });
+                }
+            }
+            StateChange::SoftDrop => self.soft_drop = None,
+            _ => {}
+        }
+    }
+
+    /// Advances the held keys by one [`FRAME`]. Returns the repeats that became due.
+    /// Instant repeats cross a board `width` by `height` cells, buffer included, in one go.
+    pub fn tick(&mut self, width: usize, height: usize) -> Vec<StateChange> {
+        let mut changes = Vec::new();
+        let Handling {
+            das,
+            arr,
+            soft_drop,
+        } = self.handling;
+        if let Some(direction) = self.direction {
+            if let Some(held) = self.held_mut(direction) {
+                held.held_for += FRAME;
+                if held.held_for >= das {
+                    let count = if arr.is_zero() {
+                        width as u32
+                    } else {
+                        let due = 1 + ((held.held_for - das).as_nanos() / arr.as_nanos()) as u32;
+                        let count = due - held.repeats;
+                        held.repeats = due;
+                        count
+                    };
+                    changes.extend((0..count).map(|_| StateChange::Move(direction)));
+                }
+            }
+        }
+        if let Some(held) = &mut self.soft_drop {
+            held.held_for += FRAME;
+            let count = if soft_drop.is_zero() {
+                height as u32
+            } else {
+                let due = (held.held_for.as_nanos() / soft_drop.as_nanos()) as u32;
+                let count = due - held.repeats;
+                held.repeats = due;
+                count
+            };
+            changes.extend((0..count).map(|_| StateChange::SoftDrop));
+        }
+        changes
+    }
+
+    fn held_mut(&mut self, direction: MovementType) -> &mut Option<Held> {
+        match direction {
+            MovementType::Left => &mut self.left,
+            MovementType::Right => &mut self.right,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{AppState, Config};
+
+    /// DAS of 10 frames and ARR of 2, so repeats land on exact frames.
+    fn controls() -> Controls {
+        Controls::new(Handling {
+            das: FRAME * 10,
+            arr: FRAME * 2,
+            soft_drop: FRAME * 3,
+        })
+    }
+
+    /// The frames, counting from 1, on which `controls` repeated `change`.
+    fn repeats(controls: &mut Controls, frames: u32, change: StateChange) -> Vec<u32> {
+        (1..=frames)
+            .flat_map(|frame| {
+                let count = controls
+                    .tick(10, 40)
+                    .into_iter()
+                    .filter(|repeat| *repeat == change)
+                    .count();
+                std::iter::repeat_n(frame, count)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn held_moves_repeat_after_das_at_the_arr() {
+        let mut controls = controls();
+        let right = StateChange::Move(MovementType::Right);
+        assert_eq!(controls.press(right), [right]);
+        // OS key repeat presses the key again, which does nothing
+        assert!(controls.press(right).is_empty());
+        assert_eq!(repeats(&mut controls, 20, right), [10, 12, 14, 16, 18, 20]);
+        controls.release(right);
+        assert!(repeats(&mut controls, 20, right).is_empty());
+    }
+
+    #[test]
+    fn the_latest_direction_wins_and_releasing_it_recharges_the_other() {
+        let mut controls = controls();
+        let (left, right) = (
+            StateChange::Move(MovementType::Left),
+            StateChange::Move(MovementType::Right),
+        );
+        controls.press(left);
+        assert!(repeats(&mut controls, 5, left).is_empty());
+        assert_eq!(controls.press(right), [right]);
+        let changes = (0..10)
+            .flat_map(|_| controls.tick(10, 40))
+            .collect::<Vec<_>>();
+        assert_eq!(changes, [right]);
+        controls.release(right);
+        assert_eq!(repeats(&mut controls, 10, left), [10]);
+    }
+
+    #[test]
+    fn soft_drop_repeats_at_its_own_rate() {
+        let mut controls = controls();
+        assert_eq!(
+            controls.press(StateChange::SoftDrop),
+            [StateChange::SoftDrop]
+        );
+        assert_eq!(repeats(&mut controls, 9, StateChange::SoftDrop), [3, 6, 9]);
+    }
+
+    #[test]
+    fn zero_arr_and_soft_drop_cross_the_board_at_once() {
+        let mut state = AppState::from_seed(Config::default(), 0);
+        let mut controls = Controls::new(Handling {
+            das: FRAME * 10,
+            arr: Duration::ZERO,
+            soft_drop: Duration::ZERO,
+        });
+        let right = StateChange::Move(MovementType::Right);
+        for change in controls.press(right) {
+            state.apply(change);
+        }
+        for _ in 0..10 {
+            for change in controls.tick(state.width(), state.board.num_rows()) {
+                state.apply(change);
+            }
+        }
+        let mut moved = state.clone();
+        moved.apply(right);
+        assert_eq!(moved.location, state.location, "not against the right wall");
+
+        controls.release(right);
+        for change in controls.press(StateChange::SoftDrop) {
+            state.apply(change);
+        }
+        for change in controls.tick(state.width(), state.board.num_rows()) {
+            state.apply(change);
+        }
+        assert_eq!(state.location.1, state.ghost_location());
+    }
+}
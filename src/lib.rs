@@ -0,0 +1,31 @@
+//! Headless Tetris rules. Frontends drive an [`AppState`] with [`StateChange`]s and
+//! gravity ticks, and draw whatever the state looks like afterwards.
+pub mod ai;
+pub mod game;
+pub mod input;
+pub mod mode;
+pub mod net;
+pub mod pieces;
+pub mod randomizer;
+pub mod replay;
+pub mod scoring;
+pub mod tbp;
+pub mod tetromino;
+
+pub use ai::{Bot, Features, Placement, Weights};
+pub use game::{
+    guideline_gravity, AppState, Cell, Config, Event, Loss, MovementType, ParseStateChangeError,
+    StateChange, COLS, FRAME, LINES_PER_LEVEL, ROWS,
+};
+pub use input::{Controls, Handling};
+pub use mode::{Mode, ParseModeError, CHEESE_LINES, SPRINT_LINES, ULTRA_SECONDS};
+pub use net::{Connection, Message, NetError, Snapshot, PROTOCOL_VERSION};
+pub use pieces::{PieceDef, PieceSet, PieceSetError, TETROMINOES};
+pub use randomizer::{
+    Bag, History, Nes, ParseRandomizerError, Random, Randomizer, RandomizerKind, TGM_HISTORY,
+    TGM_REROLLS,
+};
+pub use replay::{Playback, Replay, ReplayError};
+pub use scoring::{Clear, TSpin};
+pub use tbp::{Info, TbpBot, TbpError};
+pub use tetromino::{Piece, Rotation, Tetromino};
@@ -1,234 +1,269 @@
+mod bench;
+mod bindings;
+mod cli;
+mod highscores;
 mod rendering;
-mod tetromino;
+mod scheduler;
+mod session;
 mod window;
 
-use array2d::Array2D;
-use femtovg::{renderer::OpenGl, Canvas, Color};
+use femtovg::{renderer::OpenGl, Canvas};
 use glutin::{
     context::PossiblyCurrentContext,
     surface::{Surface, WindowSurface},
 };
-use rand::{rngs::ThreadRng, seq::SliceRandom, thread_rng};
-use std::time::{Duration, Instant};
+use std::time::Instant;
 use winit::application::ApplicationHandler;
-use winit::event::{ElementState, KeyEvent, StartCause, WindowEvent};
+use winit::event::{ElementState, KeyEvent, WindowEvent};
 use winit::event_loop::ControlFlow::WaitUntil;
-use winit::event_loop::{ActiveEventLoop, ControlFlow, EventLoop};
-use winit::keyboard::{Key, NamedKey};
+use winit::event_loop::{ActiveEventLoop, EventLoop};
 use winit::window::{Window, WindowId};
 
+use std::net::TcpListener;
+use std::process::Command;
+use std::sync::Arc;
+use tetris::{Bot, Config, Connection, PieceSet, Replay, TbpBot};
+
 use crate::{
+    bindings::{Action, Bindings},
+    cli::{parse_args, USAGE},
+    highscores::HighScores,
     rendering::render,
-    tetromino::{Piece, Rotation, Tetromino},
+    scheduler::Scheduler,
+    session::{Autoplay, Driver, Live, Online, Session, Versus, Viewer},
     window::{create_canvas, create_window},
 };
 
-const ROWS: usize = 20;
-const COLS: usize = 10;
-
-fn handle_keyboard_input(event: KeyEvent, state: &mut AppState, window: &Window) {
-    if state.game_over || event.state == ElementState::Released {
+fn handle_keyboard_input(
+    event: KeyEvent,
+    session: &mut Session,
+    bindings: &[Bindings],
+    window: &Window,
+) {
+    if let Session::Watch(viewer) = session {
+        if event.state == ElementState::Pressed {
+            viewer.handle_key(&event.logical_key);
+            window.request_redraw();
+        }
         return;
     }
-    match event.logical_key {
-        Key::Named(x) => match x {
-            NamedKey::ArrowUp => {
-                state_change(state, StateChange::Rotate(MovementType::Right), window);
-            }
-            NamedKey::ArrowLeft => {
-                state_change(state, StateChange::Move(MovementType::Left), window);
-            }
-            NamedKey::ArrowRight => {
-                state_change(state, StateChange::Move(MovementType::Right), window);
-            }
-            NamedKey::ArrowDown => {
-                state_change(state, StateChange::SoftDrop, window);
-            }
-            NamedKey::Space => {
-                state_change(state, StateChange::HardDrop, window);
+    // Each player's keys are distinct, so at most one set matches
+    let Some((seat, action)) = bindings
+        .iter()
+        .enumerate()
+        .find_map(|(seat, bindings)| Some((seat, bindings.action(event.physical_key)?)))
+    else {
+        return;
+    };
+    match session {
+        Session::Live(live) => match (action, event.state) {
+            (_, ElementState::Pressed) if event.repeat => {}
+            (Action::Pause, ElementState::Pressed) => live.toggle_pause(),
+            (Action::Restart, ElementState::Pressed) => live.restart(),
+            (Action::Game(change), ElementState::Released) => live.controls.release(change),
+            (Action::Game(change), ElementState::Pressed) => {
+                if live.state.game_over || live.paused {
+                    return;
+                }
+                for change in live.controls.press(change) {
+                    live.apply(change);
+                }
             }
-            NamedKey::Control => {
-                state_change(state, StateChange::Rotate(MovementType::Left), window);
+            (_, ElementState::Released) => {}
+        },
+        Session::Online(online) => match (action, event.state) {
+            (_, ElementState::Pressed) if event.repeat => {}
+            (Action::Restart, ElementState::Pressed) => online.restart(),
+            (Action::Game(change), ElementState::Released) => {
+                online.player.controls.release(change)
             }
-            NamedKey::Shift => {
-                state_change(state, StateChange::HoldPiece, window);
+            (Action::Game(change), ElementState::Pressed) => {
+                if online.is_over() {
+                    return;
+                }
+                for change in online.player.controls.press(change) {
+                    online.apply(change);
+                }
             }
+            // The other side can't be paused
+            (Action::Pause, _) | (_, ElementState::Released) => {}
+        },
+        Session::Autoplay(autoplay) => match (action, event.state) {
+            (_, ElementState::Pressed) if event.repeat => {}
+            (Action::Pause, ElementState::Pressed) => autoplay.toggle_pause(),
+            (Action::Restart, ElementState::Pressed) => autoplay.restart(),
             _ => {}
         },
-        Key::Character(x) => match x.as_str() {
-            "x" => {
-                state_change(state, StateChange::Rotate(MovementType::Right), window);
-            }
-            "z" => {
-                state_change(state, StateChange::Rotate(MovementType::Left), window);
+        Session::Versus(versus) => match (action, event.state) {
+            (_, ElementState::Pressed) if event.repeat => {}
+            (Action::Pause, ElementState::Pressed) => versus.toggle_pause(),
+            (Action::Restart, ElementState::Pressed) => versus.restart(),
+            (Action::Game(change), ElementState::Released) => {
+                versus.players[seat].controls.release(change)
             }
-            "c" => {
-                state_change(state, StateChange::HoldPiece, window);
+            (Action::Game(change), ElementState::Pressed) => {
+                if versus.is_over() || versus.paused {
+                    return;
+                }
+                for change in versus.players[seat].controls.press(change) {
+                    versus.apply(seat, change);
+                }
             }
-            _ => {}
+            (_, ElementState::Released) => {}
         },
-        _ => {}
-    }
-}
-#[derive(Debug, Clone)]
-enum StateChange {
-    Rotate(MovementType),
-    HoldPiece,
-    HardDrop,
-    SoftDrop,
-    Move(MovementType),
-}
-fn state_change(state: &mut AppState, change: StateChange, window: &Window) {
-    match change {
-        StateChange::Rotate(rotation_type) => rotate_piece(state, rotation_type),
-        StateChange::HoldPiece => hold_piece(state),
-        StateChange::HardDrop => hard_drop(state),
-        StateChange::SoftDrop => { move_down(state); }
-        StateChange::Move(movement_type) => move_piece(state, movement_type),
+        Session::Watch(_) => {}
     }
     window.request_redraw();
 }
-fn hard_drop(state: &mut AppState) {
-    while !move_down(state) {}
-}
-
-fn hold_piece(state: &mut AppState) {
-    if state.can_hold {
-        let held = match state.held.take() {
-            Some(x) => x,
-            None => randomize_piece(state),
-        };
-        state.held = Some(state.piece.piece);
-        state.piece.piece = held;
-        state.piece.rotation = Rotation::default();
-        state.location = (3, -3);
-        state.can_hold = false;
-    }
-}
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum MovementType {
-    Right,
-    Left,
-}
-fn piece_is_legal(state: &mut AppState) -> bool {
-    state
-        .piece
-        .to_blocks()
-        .enumerate_row_major()
-        .filter_map(|(el, x)| if *x { Some(el) } else { None })
-        .all(|(row, col)| {
-            let row = row as isize + state.location.1;
-            let col = col as isize + state.location.0;
 
-            if col < 0 || col >= COLS as isize {
-                return false;
-            }
-            if row >= ROWS as isize {
-                return false;
+const FONT: &[u8; 834452] = include_bytes!("font/Times New Roman.ttf");
+fn main() {
+    let options = match parse_args(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("{err}\n{USAGE}");
+            std::process::exit(2);
+        }
+    };
+    let driver = options.tbp.as_ref().map(|command| {
+        let (program, args) = command.split_first().expect("--tbp is never empty");
+        match TbpBot::spawn(Command::new(program).args(args)) {
+            Ok(bot) => Driver::External(bot),
+            Err(err) => {
+                eprintln!("Could not start {program}: {err}");
+                std::process::exit(1);
             }
-            if row < 0 {
-                return true;
+        }
+    });
+    let defaults = Config::default();
+    let (width, height) = options.board.unwrap_or((defaults.width, defaults.height));
+    let pieces = match &options.pieces {
+        Some(path) => match PieceSet::load(path) {
+            Ok(pieces) => Arc::new(pieces),
+            Err(err) => {
+                eprintln!("Could not load pieces {}: {err}", path.display());
+                std::process::exit(1);
             }
-            match state.board.get(row as usize, col as usize) {
-                Some(Some(_)) => false,
-                Some(None) => true,
-                None => true,
+        },
+        None => defaults.pieces.clone(),
+    };
+    // Matches against other players don't use the game mode
+    let match_config = Config {
+        width,
+        height,
+        pieces: pieces.clone(),
+        randomizer: options.randomizer.unwrap_or(defaults.randomizer),
+        ..defaults.clone()
+    };
+    let config = Config {
+        mode: options.mode,
+        hole_change: options.hole_change.unwrap_or(defaults.hole_change),
+        cheese_height: options.cheese_height.unwrap_or(defaults.cheese_height),
+        gravity: options.mode.gravity(),
+        width,
+        height,
+        pieces,
+        randomizer: options.randomizer.unwrap_or(options.mode.randomizer()),
+        ..defaults
+    };
+    if let Some(games) = options.bench {
+        let mut driver = driver.unwrap_or(Driver::Builtin(Bot::default()));
+        bench::run(&mut driver, games, options.seed.unwrap_or_default(), config);
+        return;
+    }
+    let bindings = if options.versus {
+        Bindings::load_versus()
+    } else {
+        Bindings::load().map(|bindings| vec![bindings])
+    };
+    let bindings = match bindings {
+        Ok(bindings) => bindings,
+        Err(err) => {
+            eprintln!("Invalid controls: {err}");
+            std::process::exit(1);
+        }
+    };
+    let session = match options.replay {
+        Some(path) => match Replay::load(&path) {
+            Ok(replay) => Session::Watch(Viewer::new(replay)),
+            Err(err) => {
+                eprintln!("Could not load replay {}: {err}", path.display());
+                std::process::exit(1);
             }
-        })
-}
-fn move_piece(state: &mut AppState, movement_type: MovementType) {
-    match movement_type {
-        MovementType::Left => {
-            state.location.0 -= 1;
-            if !piece_is_legal(state) {
-                state.location.0 += 1;
+        },
+        None if options.host.is_some() || options.join.is_some() => {
+            let connection = match (&options.host, &options.join) {
+                (Some(address), _) => {
+                    TcpListener::bind(address)
+                        .map_err(Into::into)
+                        .and_then(|listener| {
+                            let address = listener.local_addr()?;
+                            println!("Waiting for an opponent on {address}");
+                            Connection::host(&listener)
+                        })
+                }
+                (None, Some(address)) => Connection::join(address),
+                (None, None) => unreachable!(),
+            };
+            match connection {
+                Ok(connection) => {
+                    Session::Online(Online::new(connection, bindings[0].handling, match_config))
+                }
+                Err(err) => {
+                    eprintln!("Could not start the match: {err}");
+                    std::process::exit(1);
+                }
             }
         }
-        MovementType::Right => {
-            state.location.0 += 1;
-            if !piece_is_legal(state) {
-                state.location.0 -= 1;
-            }
+        None if options.ai || driver.is_some() => {
+            let driver = driver.unwrap_or(Driver::Builtin(Bot::default()));
+            Session::Autoplay(Autoplay::new(config, options.seed, driver))
+        }
+        None if options.versus => {
+            let handling = bindings.iter().map(|bindings| bindings.handling).collect();
+            Session::Versus(Versus::new(handling, options.seed, match_config))
+        }
+        None => {
+            let scores = HighScores::load().unwrap_or_else(|err| {
+                eprintln!("Ignoring high scores: {err}");
+                HighScores::default()
+            });
+            let player = options
+                .name
+                .or_else(|| std::env::var("USER").ok())
+                .or_else(|| std::env::var("USERNAME").ok())
+                .unwrap_or_else(|| "Player".to_string());
+            Session::Live(Live::new(
+                config,
+                options.seed,
+                bindings[0].handling,
+                options.record,
+                scores,
+                player,
+            ))
         }
-    }
-}
-fn rotate_piece(state: &mut AppState, rotation_type: MovementType) {
-    let old = state.piece.rotation;
-    state.piece.rotation = match rotation_type {
-        MovementType::Right => match state.piece.rotation {
-            Rotation::Up => Rotation::Right,
-            Rotation::Right => Rotation::Down,
-            Rotation::Down => Rotation::Left,
-            Rotation::Left => Rotation::Up,
-        },
-        MovementType::Left => match state.piece.rotation {
-            Rotation::Up => Rotation::Left,
-            Rotation::Left => Rotation::Down,
-            Rotation::Down => Rotation::Right,
-            Rotation::Right => Rotation::Up,
-        },
     };
-    if !piece_is_legal(state) {
-        state.piece.rotation = old;
-    }
-}
-
-#[derive(Debug, Clone)]
-struct AppState {
-    board: Array2D<Option<Color>>,
-    piece: Tetromino,
-    location: (isize, isize),
-    bag: Vec<Piece>,
-    game_over: bool,
-    score: u64,
-    rng: ThreadRng,
-    held: Option<Piece>,
-    can_hold: bool,
-}
-impl AppState {
-    fn new() -> Self {
-        let mut tmp = AppState {
-            board: Array2D::filled_with(None, ROWS, COLS),
-            piece: Tetromino {
-                piece: Piece::I,
-                rotation: Rotation::default(),
-            },
-            location: (3, -3),
-            bag: vec![
-                Piece::I,
-                Piece::J,
-                Piece::L,
-                Piece::O,
-                Piece::S,
-                Piece::Z,
-                Piece::T,
-            ],
-            game_over: false,
-            score: 0,
-            rng: thread_rng(),
-            held: None,
-            can_hold: true,
-        };
-        tmp.piece.piece = randomize_piece(&mut tmp);
-        tmp
-    }
-}
 
-const FONT: &[u8; 834452] = include_bytes!("font/Times New Roman.ttf");
-fn main() {
+    // Only open the window once any opponent has connected
     let event_loop = EventLoop::new().expect("Could not create event loop");
     let (context, gl_display, window, surface) = create_window(&event_loop);
     let mut canvas = create_canvas(gl_display, &window);
-    let mut state = AppState::new();
 
     canvas
         .add_font_mem(FONT)
         .expect("Unable to load font from memory");
 
-    render(&context, &surface, &window, &mut canvas, &mut state);
-    wait(&event_loop);
+    render(
+        &context,
+        &surface,
+        &window,
+        &mut canvas,
+        session.boards(&bindings),
+    );
     let mut game = Game {
-        state: AppState::new(),
+        session,
+        bindings,
+        scheduler: Scheduler::new(),
         window,
         context,
         surface,
@@ -236,42 +271,26 @@ fn main() {
     };
     event_loop.run_app(&mut game).unwrap();
 }
-trait SetControlFlow {
-    fn set_control_flow(&self, control_flow: ControlFlow);
-}
-impl<T> SetControlFlow for EventLoop<T> {
-    fn set_control_flow(&self, flow: ControlFlow) {
-        self.set_control_flow(flow);
-    }
-}
-impl SetControlFlow for ActiveEventLoop {
-    fn set_control_flow(&self, flow: ControlFlow) {
-        self.set_control_flow(flow);
-    }
-}
-fn wait<T>(event_loop: &T)
-where
-    T: SetControlFlow,
-{
-    event_loop.set_control_flow(WaitUntil(Instant::now() + Duration::from_millis(333)));
-}
-
 struct Game {
-    state: AppState,
+    session: Session,
+    /// Keys for each player. Only the first is used outside a local match.
+    bindings: Vec<Bindings>,
+    scheduler: Scheduler,
     window: Window,
     context: PossiblyCurrentContext,
     surface: Surface<WindowSurface>,
     canvas: Canvas<OpenGl>,
 }
 impl ApplicationHandler for Game {
-    fn new_events(&mut self, event_loop: &ActiveEventLoop, cause: StartCause) {
-        match cause {
-            StartCause::ResumeTimeReached { .. } => {
-                step_game(event_loop, &mut self.state);
-                self.window.request_redraw();
-            }
-            _ => {}
-        };
+    fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
+        let frames = self.scheduler.due_frames(Instant::now());
+        for _ in 0..frames {
+            self.session.step();
+        }
+        if frames > 0 {
+            self.window.request_redraw();
+        }
+        event_loop.set_control_flow(WaitUntil(self.scheduler.next_frame()));
     }
 
     fn resumed(&mut self, event_loop: &ActiveEventLoop) {
@@ -283,152 +302,34 @@ impl ApplicationHandler for Game {
         _window_id: WindowId,
         event: WindowEvent,
     ) {
-        handle_window_event(
-            &mut self.state,
-            event,
-            &self.context,
-            &self.surface,
-            &self.window,
-            &mut self.canvas,
-            event_loop,
-        )
-    }
-}
-fn step_game(event_loop: &ActiveEventLoop, state: &mut AppState) {
-    if !state.game_over {
-        move_down(state);
-        wait(event_loop);
+        handle_window_event(self, event, event_loop)
     }
 }
-fn move_down(state: &mut AppState) -> bool {
-    state.location.1 += 1;
-    if !piece_is_legal(state) {
-        state.location.1 -= 1;
-        if lock_piece(state) {
-            return true;
-        }
-        state.location = (3, -3);
-        state.piece.piece = randomize_piece(state);
-        state.piece.rotation = Rotation::default();
-        return true;
-    }
-    false
-}
-
-fn lock_piece(state: &mut AppState) -> bool {
-    let mut count = 0;
-    state.can_hold = true;
-    let location = state.location;
-    if state
-        .piece
-        .to_blocks()
-        .enumerate_row_major()
-        .filter_map(|((row, col), x)| {
-            if *x {
-                Some((row as isize + location.1, col as isize + location.0))
-            } else {
-                None
-            }
-        })
-        .any(|(row, col)| {
-            if row < 0 {
-                state.game_over = true;
-                return true;
-            }
-            state
-                .board
-                .set(row as usize, col as usize, Some(state.piece.to_color()))
-                .expect("Unable to set block");
-            count += clear_lines(state);
-            false
-        })
-    {
-        return true;
-    }
-    state.score += match count {
-        0 => 0,
-        1 => 100,
-        2 => 300,
-        3 => 500,
-        4 => 800,
-        _ => unreachable!(),
-    };
-    false
-}
-
-fn clear_lines(state: &mut AppState) -> u8 {
-    let mut count = 0;
-    let rows = {
-        let mut tmp = state
-            .board
-            .rows_iter()
-            .filter_map(|x| {
-                if x.clone().all(|y| y.is_some()) {
-                    count += 1;
-                    None
-                } else {
-                    Some(x.copied().collect::<Vec<_>>())
-                }
-            })
-            .collect::<Vec<_>>();
-        while tmp.len() < ROWS {
-            tmp.insert(0, vec![None; COLS])
-        }
-        tmp
-    };
-    state.board = Array2D::from_rows(&rows).unwrap();
-    count
-}
-fn randomize_piece(state: &mut AppState) -> Piece {
-    match state.bag.choose(&mut state.rng) {
-        Some(x) => {
-            let tmp = *x;
-            state.bag.retain(|x| *x != tmp);
-            tmp
-        }
-        None => {
-            state.bag = vec![
-                Piece::I,
-                Piece::J,
-                Piece::L,
-                Piece::O,
-                Piece::S,
-                Piece::Z,
-                Piece::T,
-            ];
-            match state.bag.choose(&mut thread_rng()) {
-                Some(x) => {
-                    let tmp = *x;
-                    state.bag.retain(|x| *x != tmp);
-                    tmp
-                }
-                None => {
-                    unreachable!()
-                }
-            }
-        }
-    }
-}
-
-fn handle_window_event(
-    state: &mut AppState,
-    event: WindowEvent,
-    context: &PossiblyCurrentContext,
-    surface: &Surface<WindowSurface>,
-    window: &Window,
-    canvas: &mut Canvas<OpenGl>,
-    event_loop: &ActiveEventLoop,
-) {
+fn handle_window_event(game: &mut Game, event: WindowEvent, event_loop: &ActiveEventLoop) {
+    let Game {
+        session,
+        bindings,
+        window,
+        scheduler: _,
+        context,
+        surface,
+        canvas,
+    } = game;
     match event {
         WindowEvent::RedrawRequested => {
-            render(&context, &surface, &window, canvas, state);
+            render(context, surface, window, canvas, session.boards(bindings));
+        }
+        WindowEvent::CloseRequested => {
+            if let Session::Live(live) = session {
+                live.save_recording();
+            }
+            event_loop.exit()
         }
-        WindowEvent::CloseRequested => event_loop.exit(),
         WindowEvent::KeyboardInput {
             device_id: _,
             event,
             is_synthetic: _,
-        } => handle_keyboard_input(event, state, window),
+        } => handle_keyboard_input(event, session, bindings, window),
         _ => {}
     }
 }
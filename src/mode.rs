@@ -0,0 +1,110 @@
+//! Game modes decide when a game is won and how finished games are ranked.
+use std::{fmt, str::FromStr, time::Duration};
+use thiserror::Error;
+
+use crate::game::guideline_gravity;
+use crate::randomizer::RandomizerKind;
+
+/// Lines a sprint asks for unless told otherwise.
+pub const SPRINT_LINES: u32 = 40;
+/// Length of an ultra game in seconds unless told otherwise.
+pub const ULTRA_SECONDS: u32 = 120;
+/// Garbage rows to dig through in cheese mode unless told otherwise.
+pub const CHEESE_LINES: u32 = 18;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Mode {
+    /// Endless play until the stack tops out, ranked by score.
+    #[default]
+    Marathon,
+    /// Clear `lines` lines as fast as possible, ranked by time.
+    Sprint { lines: u32 },
+    /// Score as much as possible in `seconds` seconds, ranked by score.
+    Ultra { seconds: u32 },
+    /// Dig through `lines` garbage rows as fast as possible, ranked by time.
+    Cheese { lines: u32 },
+}
+impl Mode {
+    /// Whether finished games are ranked by fastest time rather than highest score.
+    pub fn ranked_by_time(self) -> bool {
+        matches!(self, Mode::Sprint { .. } | Mode::Cheese { .. })
+    }
+    /// Lines to clear to win, for modes that have a goal. Cheese mode only counts
+    /// garbage rows.
+    pub fn goal_lines(self) -> Option<u32> {
+        match self {
+            Mode::Sprint { lines } | Mode::Cheese { lines } => Some(lines),
+            Mode::Marathon | Mode::Ultra { .. } => None,
+        }
+    }
+    /// How the mode deals pieces unless the player picks another randomizer. Races use
+    /// the 7-bag so every run gets the same fair pieces. Ultra deals from a 14-bag, which
+    /// allows the odd repeat and drought to plan around, and cheese uses the TGM history,
+    /// which never floods a dig with one piece but can't be counted like a bag.
+    pub fn randomizer(self) -> RandomizerKind {
+        match self {
+            Mode::Marathon | Mode::Sprint { .. } => RandomizerKind::SevenBag,
+            Mode::Ultra { .. } => RandomizerKind::FourteenBag,
+            Mode::Cheese { .. } => RandomizerKind::Tgm,
+        }
+    }
+    /// Gravity per level for [`Config::gravity`](crate::Config::gravity). Marathon and
+    /// ultra speed up along the guideline curve. Races are about how fast the player
+    /// places pieces rather than how long they survive, so they keep the level 1 speed.
+    pub fn gravity(self) -> Vec<f32> {
+        let mut gravity = guideline_gravity();
+        match self {
+            Mode::Marathon | Mode::Ultra { .. } => {}
+            Mode::Sprint { .. } | Mode::Cheese { .. } => gravity.truncate(1),
+        }
+        gravity
+    }
+    /// How long the game lasts, for modes that end on time.
+    pub fn time_limit(self) -> Option<Duration> {
+        match self {
+            Mode::Ultra { seconds } => Some(Duration::from_secs(seconds.into())),
+            Mode::Marathon | Mode::Sprint { .. } | Mode::Cheese { .. } => None,
+        }
+    }
+}
+/// Written as `marathon`, `sprint-<lines>`, `ultra-<seconds>` or `cheese-<lines>`.
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Mode::Marathon => f.write_str("marathon"),
+            Mode::Sprint { lines } => write!(f, "sprint-{lines}"),
+            Mode::Ultra { seconds } => write!(f, "ultra-{seconds}"),
+            Mode::Cheese { lines } => write!(f, "cheese-{lines}"),
+        }
+    }
+}
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("unknown mode {0:?}")]
+pub struct ParseModeError(pub String);
+/// Accepts what [`Display`](fmt::Display) writes, plus `sprint`, `ultra` and `cheese`
+/// for the default [`SPRINT_LINES`], [`ULTRA_SECONDS`] and [`CHEESE_LINES`].
+impl FromStr for Mode {
+    type Err = ParseModeError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, amount) = match s.split_once('-') {
+            Some((name, amount)) => match amount.parse() {
+                Ok(amount @ 1..) => (name, Some(amount)),
+                _ => return Err(ParseModeError(s.to_string())),
+            },
+            None => (s, None),
+        };
+        match (name, amount) {
+            ("marathon", None) => Ok(Mode::Marathon),
+            ("sprint", lines) => Ok(Mode::Sprint {
+                lines: lines.unwrap_or(SPRINT_LINES),
+            }),
+            ("ultra", seconds) => Ok(Mode::Ultra {
+                seconds: seconds.unwrap_or(ULTRA_SECONDS),
+            }),
+            ("cheese", lines) => Ok(Mode::Cheese {
+                lines: lines.unwrap_or(CHEESE_LINES),
+            }),
+            _ => Err(ParseModeError(s.to_string())),
+        }
+    }
+}
@@ -0,0 +1,324 @@
+//! Versus over TCP. Each side runs its own game and tells the other what its board looks
+//! like and how much garbage it sends. Neither side simulates the other, so the two games
+//! never need to agree on timing.
+//!
+//! The protocol is line based text, one message per line, in both directions:
+//!
+//! ```text
+//! tetris-versus 3
+//! state 1200 8 1 2 T S up 3 25 ZOIJL 10 20 20 ..........(400 cells)
+//! garbage 4
+//! game-over
+//! restart
+//! ```
+//!
+//! - `tetris-versus <version>` is the first line each side sends. A connection whose
+//!   first line isn't this with [`PROTOCOL_VERSION`] is dropped.
+//! - `state <score> <lines> <level> <pending garbage> <held> <piece> <rotation> <col>
+//!   <row> <queue> <width> <height> <buffer> <board>` is everything needed to draw the
+//!   sender's field. It is sent whenever any of it changes. `<held>` is a piece letter
+//!   or `-`, `<rotation>` is `up`, `right`, `down` or `left`, `<queue>` is the upcoming
+//!   piece letters or `-`, and `<board>` is every cell from the top of the buffer down,
+//!   left to right, as `.` for empty, `G` for garbage or the letter of the piece that
+//!   locked there.
+//!   The two sides' boards don't have to be the same size.
+//! - `garbage <lines>` sends garbage to the receiver, who queues it with
+//!   [`AppState::receive_garbage`].
+//! - `game-over` means the sender topped out, which ends the match.
+//! - `restart` means the sender started a new match, and the receiver should too.
+use array2d::Array2D;
+use std::{
+    fmt,
+    io::{self, BufRead, BufReader, Write},
+    net::{Shutdown, TcpListener, TcpStream, ToSocketAddrs},
+    str::FromStr,
+    sync::mpsc::{self, Receiver, TryRecvError},
+    thread,
+};
+use thiserror::Error;
+
+use crate::game::{AppState, Cell};
+use crate::tetromino::{Piece, Rotation, Tetromino};
+
+pub const PROTOCOL_VERSION: u32 = 3;
+const MAGIC: &str = "tetris-versus";
+
+#[derive(Debug, Error)]
+pub enum NetError {
+    #[error("connection failed: {0}")]
+    Io(#[from] io::Error),
+    #[error("the other side is not a tetris game")]
+    NotAPeer,
+    #[error("the other side speaks protocol version {0}, expected {PROTOCOL_VERSION}")]
+    Version(u32),
+    #[error("bad message {0:?}")]
+    Parse(String),
+    #[error("the other side disconnected")]
+    Disconnected,
+}
+
+/// What one side can see of the other's game.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    pub score: u64,
+    pub lines: u32,
+    pub level: u32,
+    pub pending_garbage: u32,
+    pub held: Option<Piece>,
+    pub piece: Tetromino,
+    pub location: (isize, isize),
+    pub queue: Vec<Piece>,
+    pub width: usize,
+    /// Visible rows, and hidden rows above them.
+    pub height: usize,
+    pub buffer: usize,
+    /// Every cell, top of the buffer first.
+    pub board: Vec<Option<Cell>>,
+}
+impl Snapshot {
+    pub fn of(state: &AppState) -> Self {
+        Snapshot {
+            score: state.score,
+            lines: state.lines,
+            level: state.level,
+            pending_garbage: state.pending_garbage,
+            held: state.held,
+            piece: state.piece,
+            location: state.location,
+            queue: state.queue.iter().copied().collect(),
+            width: state.width(),
+            height: state.height(),
+            buffer: state.buffer(),
+            board: state.board.elements_row_major_iter().copied().collect(),
+        }
+    }
+    /// Makes `state` look like the game this was taken from, so it can be drawn.
+    pub fn apply_to(&self, state: &mut AppState) {
+        state.score = self.score;
+        state.lines = self.lines;
+        state.level = self.level;
+        state.pending_garbage = self.pending_garbage;
+        state.held = self.held;
+        state.piece = self.piece;
+        state.location = self.location;
+        state.queue = self.queue.iter().copied().collect();
+        state.config.buffer = self.buffer;
+        state.board = Array2D::from_row_major(&self.board, self.buffer + self.height, self.width)
+            .expect("snapshot boards are checked when parsed");
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    State(Snapshot),
+    Garbage(u32),
+    GameOver,
+    Restart,
+}
+impl fmt::Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Message::State(snapshot) => {
+                let Snapshot {
+                    score,
+                    lines,
+                    level,
+                    pending_garbage,
+                    held,
+                    piece,
+                    location: (col, row),
+                    queue,
+                    width,
+                    height,
+                    buffer,
+                    board,
+                } = snapshot;
+                let held = held.map_or('-', Piece::to_char);
+                let rotation = match piece.rotation {
+                    Rotation::Up => "up",
+                    Rotation::Right => "right",
+                    Rotation::Down => "down",
+                    Rotation::Left => "left",
+                };
+                let queue = if queue.is_empty() {
+                    "-".to_string()
+                } else {
+                    queue.iter().copied().map(Piece::to_char).collect()
+                };
+                let board = board
+                    .iter()
+                    .map(|cell| match cell {
+                        None => '.',
+                        Some(Cell::Garbage) => 'G',
+                        Some(Cell::Block(piece)) => piece.to_char(),
+                    })
+                    .collect::<String>();
+                write!(
+                    f,
+                    "state {score} {lines} {level} {pending_garbage} {held} {} {rotation} \
+                     {col} {row} {queue} {width} {height} {buffer} {board}",
+                    piece.piece.to_char(),
+                )
+            }
+            Message::Garbage(lines) => write!(f, "garbage {lines}"),
+            Message::GameOver => f.write_str("game-over"),
+            Message::Restart => f.write_str("restart"),
+        }
+    }
+}
+impl FromStr for Message {
+    type Err = NetError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || NetError::Parse(s.to_string());
+        let piece = |field: &str| {
+            let mut chars = field.chars();
+            match (chars.next().and_then(Piece::from_char), chars.next()) {
+                (Some(piece), None) => Ok(piece),
+                _ => Err(error()),
+            }
+        };
+        let fields = s.split_whitespace().collect::<Vec<_>>();
+        Ok(match fields.as_slice() {
+            ["state", score, lines, level, pending, held, active, rotation, col, row, queue, width, height, buffer, board] =>
+            {
+                let rotation = match *rotation {
+                    "up" => Rotation::Up,
+                    "right" => Rotation::Right,
+                    "down" => Rotation::Down,
+                    "left" => Rotation::Left,
+                    _ => return Err(error()),
+                };
+                let queue = match *queue {
+                    "-" => Vec::new(),
+                    queue => queue
+                        .chars()
+                        .map(|c| Piece::from_char(c).ok_or_else(error))
+                        .collect::<Result<_, _>>()?,
+                };
+                let board = board
+                    .chars()
+                    .map(|c| match c {
+                        '.' => Ok(None),
+                        'G' => Ok(Some(Cell::Garbage)),
+                        c => Piece::from_char(c)
+                            .map(|piece| Some(Cell::Block(piece)))
+                            .ok_or_else(error),
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                let (width, height, buffer): (usize, usize, usize) = (
+                    parse_field(width, s)?,
+                    parse_field(height, s)?,
+                    parse_field(buffer, s)?,
+                );
+                let cells = height
+                    .checked_add(buffer)
+                    .and_then(|rows| rows.checked_mul(width));
+                if height == 0 || width == 0 || cells != Some(board.len()) {
+                    return Err(error());
+                }
+                Message::State(Snapshot {
+                    score: parse_field(score, s)?,
+                    lines: parse_field(lines, s)?,
+                    level: parse_field(level, s)?,
+                    pending_garbage: parse_field(pending, s)?,
+                    held: match *held {
+                        "-" => None,
+                        held => Some(piece(held)?),
+                    },
+                    piece: Tetromino {
+                        piece: piece(active)?,
+                        rotation,
+                    },
+                    location: (parse_field(col, s)?, parse_field(row, s)?),
+                    queue,
+                    width,
+                    height,
+                    buffer,
+                    board,
+                })
+            }
+            ["garbage", lines] => Message::Garbage(parse_field(lines, s)?),
+            ["game-over"] => Message::GameOver,
+            ["restart"] => Message::Restart,
+            _ => return Err(error()),
+        })
+    }
+}
+
+fn parse_field<T: FromStr>(field: &str, message: &str) -> Result<T, NetError> {
+    field
+        .parse()
+        .map_err(|_| NetError::Parse(message.to_string()))
+}
+
+/// A connection to the other side of a match. Messages arrive on a background thread, so
+/// [`Connection::poll`] never blocks the game.
+#[derive(Debug)]
+pub struct Connection {
+    stream: TcpStream,
+    incoming: Receiver<Result<Message, NetError>>,
+}
+impl Connection {
+    /// Waits for the other side to [`join`](Connection::join) on `listener`.
+    pub fn host(listener: &TcpListener) -> Result<Self, NetError> {
+        let (stream, _) = listener.accept()?;
+        Self::handshake(stream)
+    }
+    pub fn join(address: impl ToSocketAddrs) -> Result<Self, NetError> {
+        Self::handshake(TcpStream::connect(address)?)
+    }
+    fn handshake(mut stream: TcpStream) -> Result<Self, NetError> {
+        stream.set_nodelay(true)?;
+        writeln!(stream, "{MAGIC} {PROTOCOL_VERSION}")?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut header = String::new();
+        reader.read_line(&mut header)?;
+        let version = header
+            .trim()
+            .strip_prefix(MAGIC)
+            .and_then(|version| version.trim().parse().ok())
+            .ok_or(NetError::NotAPeer)?;
+        if version != PROTOCOL_VERSION {
+            return Err(NetError::Version(version));
+        }
+
+        let (sender, incoming) = mpsc::channel();
+        thread::spawn(move || {
+            for line in reader.lines() {
+                let message = match line {
+                    Ok(line) => line.parse(),
+                    Err(err) => Err(NetError::Io(err)),
+                };
+                let failed = message.is_err();
+                if sender.send(message).is_err() || failed {
+                    return;
+                }
+            }
+            let _ = sender.send(Err(NetError::Disconnected));
+        });
+        Ok(Connection { stream, incoming })
+    }
+    pub fn send(&mut self, message: &Message) -> Result<(), NetError> {
+        writeln!(self.stream, "{message}")?;
+        Ok(())
+    }
+    /// Messages received since the last call, oldest first. An error means the connection
+    /// is no longer usable.
+    pub fn poll(&mut self) -> Result<Vec<Message>, NetError> {
+        let mut messages = Vec::new();
+        loop {
+            match self.incoming.try_recv() {
+                Ok(message) => messages.push(message?),
+                Err(TryRecvError::Empty) => return Ok(messages),
+                Err(TryRecvError::Disconnected) => return Err(NetError::Disconnected),
+            }
+        }
+    }
+}
+/// The reader thread holds its own handle to the socket, so it has to be shut down
+/// explicitly for the other side to notice.
+impl Drop for Connection {
+    fn drop(&mut self) {
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+}
@@ -0,0 +1,467 @@
+//! Piece sets. The shapes, colours, spawn offsets and kick tables of the pieces a game
+//! uses are data, read from a TOML file, so modes can play with pentominoes or any
+//! other polyominoes. The standard tetrominoes ship as `pieces/tetrominoes.toml`, which
+//! also documents the format.
+use array2d::Array2D;
+use serde::Deserialize;
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt, fs, io,
+    path::Path,
+    str::FromStr,
+    sync::{Arc, OnceLock},
+};
+use thiserror::Error;
+
+use crate::tetromino::{Piece, Rotation, Tetromino};
+
+/// The standard tetromino set, in the file format.
+pub const TETROMINOES: &str = include_str!("pieces/tetrominoes.toml");
+
+#[derive(Debug, Error)]
+pub enum PieceSetError {
+    #[error("could not read piece set: {0}")]
+    Io(#[from] io::Error),
+    #[error("invalid piece set: {0}")]
+    Toml(#[from] toml::de::Error),
+    #[error("the set has no pieces")]
+    Empty,
+    #[error("{0:?} is not a piece name, names are one letter or digit other than G")]
+    Name(String),
+    #[error("piece {0} is defined twice")]
+    Duplicate(char),
+    #[error("piece {piece}: {message}")]
+    Shape { piece: char, message: String },
+    #[error("piece {piece} uses kick table {kicks:?}, which isn't defined")]
+    UnknownKicks { piece: char, kicks: String },
+    #[error("kick table {0:?} needs 8 turns with at least one offset each")]
+    Kicks(String),
+}
+
+/// The file as written, before it is checked.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SetFile {
+    #[serde(default)]
+    kicks: BTreeMap<String, Vec<Vec<(isize, isize)>>>,
+    #[serde(default)]
+    piece: Vec<PieceFile>,
+}
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PieceFile {
+    name: String,
+    color: (u8, u8, u8),
+    kicks: String,
+    shapes: Vec<Vec<String>>,
+    #[serde(default)]
+    spawn: (isize, isize),
+}
+
+/// One piece of a set.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PieceDef {
+    pub piece: Piece,
+    pub color: (u8, u8, u8),
+    /// Cells right and up from the guideline spawn position.
+    pub spawn: (isize, isize),
+    /// Blocks in the up, right, down and left rotations.
+    shapes: [Array2D<bool>; 4],
+    /// Offsets for each turn, as `(x, y)` with y pointing up, in [`kick_index`] order.
+    kicks: [Vec<(isize, isize)>; 8],
+}
+
+/// The pieces a game is played with, in the order the randomizer deals them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PieceSet {
+    pieces: Vec<PieceDef>,
+}
+impl PieceSet {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, PieceSetError> {
+        fs::read_to_string(path)?.parse()
+    }
+    /// The standard tetrominoes, shared between every game that uses them.
+    pub fn tetrominoes() -> Arc<Self> {
+        static SET: OnceLock<Arc<PieceSet>> = OnceLock::new();
+        SET.get_or_init(|| {
+            Arc::new(
+                TETROMINOES
+                    .parse()
+                    .expect("the built in tetromino set is valid"),
+            )
+        })
+        .clone()
+    }
+    pub fn pieces(&self) -> Vec<Piece> {
+        self.pieces.iter().map(|def| def.piece).collect()
+    }
+    pub fn get(&self, piece: Piece) -> Option<&PieceDef> {
+        self.pieces.iter().find(|def| def.piece == piece)
+    }
+    /// The blocks of `tetromino` in its box, or `None` if the piece isn't in the set.
+    pub fn shape(&self, tetromino: Tetromino) -> Option<&Array2D<bool>> {
+        let def = self.get(tetromino.piece)?;
+        Some(&def.shapes[rotation_index(tetromino.rotation)])
+    }
+    /// Offsets to try, in order, when rotating `piece` from `from` to `to`. The first
+    /// offset that fits wins; its index is the kick that was used.
+    pub fn kicks(&self, piece: Piece, from: Rotation, to: Rotation) -> &[(isize, isize)] {
+        match (self.get(piece), kick_index(from, to)) {
+            (Some(def), Some(index)) => &def.kicks[index],
+            _ => &[(0, 0)],
+        }
+    }
+    /// The piece's colour, or grey for pieces from another set.
+    pub fn color(&self, piece: Piece) -> (u8, u8, u8) {
+        self.get(piece).map_or((192, 192, 192), |def| def.color)
+    }
+}
+/// Writes the set back out in the file format, one table or piece per line, so it can be
+/// stored alongside a game. Pieces that share a kick table share it in the output too.
+impl fmt::Display for PieceSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut tables: Vec<&[Vec<(isize, isize)>; 8]> = Vec::new();
+        for def in &self.pieces {
+            if !tables.contains(&&def.kicks) {
+                tables.push(&def.kicks);
+            }
+        }
+        writeln!(f, "[kicks]")?;
+        for (index, table) in tables.iter().enumerate() {
+            let turns = table
+                .iter()
+                .map(|offsets| {
+                    let offsets = offsets
+                        .iter()
+                        .map(|(x, y)| format!("[{x}, {y}]"))
+                        .collect::<Vec<_>>();
+                    format!("[{}]", offsets.join(", "))
+                })
+                .collect::<Vec<_>>();
+            writeln!(f, "kicks{index} = [{}]", turns.join(", "))?;
+        }
+        for def in &self.pieces {
+            let table = tables
+                .iter()
+                .position(|table| **table == def.kicks)
+                .expect("every piece's table was collected");
+            let shapes = def
+                .shapes
+                .iter()
+                .map(|shape| {
+                    let rows = shape
+                        .rows_iter()
+                        .map(|row| {
+                            let row = row.map(|block| if *block { '#' } else { '.' });
+                            format!("{:?}", row.collect::<String>())
+                        })
+                        .collect::<Vec<_>>();
+                    format!("[{}]", rows.join(", "))
+                })
+                .collect::<Vec<_>>();
+            let (r, g, b) = def.color;
+            let (x, y) = def.spawn;
+            writeln!(f, "[[piece]]")?;
+            writeln!(f, "name = \"{}\"", def.piece.to_char())?;
+            writeln!(f, "color = [{r}, {g}, {b}]")?;
+            writeln!(f, "kicks = \"kicks{table}\"")?;
+            writeln!(f, "spawn = [{x}, {y}]")?;
+            writeln!(f, "shapes = [{}]", shapes.join(", "))?;
+        }
+        Ok(())
+    }
+}
+impl FromStr for PieceSet {
+    type Err = PieceSetError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let file: SetFile = toml::from_str(s)?;
+        let mut kicks = BTreeMap::new();
+        for (name, turns) in file.kicks {
+            let turns: [Vec<(isize, isize)>; 8] = turns
+                .try_into()
+                .map_err(|_| PieceSetError::Kicks(name.clone()))?;
+            if turns.iter().any(Vec::is_empty) {
+                return Err(PieceSetError::Kicks(name));
+            }
+            kicks.insert(name, turns);
+        }
+
+        let mut seen = HashSet::new();
+        let mut pieces = Vec::new();
+        for piece in file.piece {
+            let mut chars = piece.name.chars();
+            let name = match (chars.next().and_then(Piece::from_char), chars.next()) {
+                (Some(name), None) => name,
+                _ => return Err(PieceSetError::Name(piece.name)),
+            };
+            if !seen.insert(name) {
+                return Err(PieceSetError::Duplicate(name.to_char()));
+            }
+            let shape_error = |message: String| PieceSetError::Shape {
+                piece: name.to_char(),
+                message,
+            };
+            let shapes = piece
+                .shapes
+                .iter()
+                .map(|rows| parse_shape(rows).map_err(shape_error))
+                .collect::<Result<Vec<_>, _>>()?;
+            let shapes: [Array2D<bool>; 4] = shapes
+                .try_into()
+                .map_err(|_| shape_error("needs exactly 4 rotations".to_string()))?;
+            if shapes
+                .iter()
+                .any(|shape| shape.num_rows() != shapes[0].num_rows())
+            {
+                return Err(shape_error("rotations have different sizes".to_string()));
+            }
+            let kicks = kicks
+                .get(&piece.kicks)
+                .ok_or_else(|| PieceSetError::UnknownKicks {
+                    piece: name.to_char(),
+                    kicks: piece.kicks.clone(),
+                })?
+                .clone();
+            pieces.push(PieceDef {
+                piece: name,
+                color: piece.color,
+                spawn: piece.spawn,
+                shapes,
+                kicks,
+            });
+        }
+        if pieces.is_empty() {
+            return Err(PieceSetError::Empty);
+        }
+        Ok(PieceSet { pieces })
+    }
+}
+
+/// A square box of `#` and `.` rows with at least one block.
+fn parse_shape(rows: &[String]) -> Result<Array2D<bool>, String> {
+    let cells = rows
+        .iter()
+        .map(|row| {
+            row.chars()
+                .map(|c| match c {
+                    '#' => Ok(true),
+                    '.' => Ok(false),
+                    c => Err(format!("unexpected {c:?} in a shape")),
+                })
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if cells.is_empty() || cells.iter().any(|row| row.len() != cells.len()) {
+        return Err("shapes must be square".to_string());
+    }
+    if !cells.iter().flatten().any(|block| *block) {
+        return Err("shapes need at least one block".to_string());
+    }
+    Ok(Array2D::from_rows(&cells).expect("rows were checked to be the same length"))
+}
+
+fn rotation_index(rotation: Rotation) -> usize {
+    match rotation {
+        Rotation::Up => 0,
+        Rotation::Right => 1,
+        Rotation::Down => 2,
+        Rotation::Left => 3,
+    }
+}
+
+/// Where a turn's offsets are in a kick table, or `None` if `from` and `to` aren't a
+/// quarter turn apart.
+fn kick_index(from: Rotation, to: Rotation) -> Option<usize> {
+    Some(match (from, to) {
+        (Rotation::Up, Rotation::Right) => 0,
+        (Rotation::Right, Rotation::Up) => 1,
+        (Rotation::Right, Rotation::Down) => 2,
+        (Rotation::Down, Rotation::Right) => 3,
+        (Rotation::Down, Rotation::Left) => 4,
+        (Rotation::Left, Rotation::Down) => 5,
+        (Rotation::Left, Rotation::Up) => 6,
+        (Rotation::Up, Rotation::Left) => 7,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOT: &str = r###"[["#"], ["#"], ["#"], ["#"]]"###;
+    const NO_KICKS: &str =
+        "[[[0, 0]], [[0, 0]], [[0, 0]], [[0, 0]], [[0, 0]], [[0, 0]], [[0, 0]], [[0, 0]]]";
+
+    /// A `[[piece]]` entry.
+    fn piece(name: &str, kicks: &str, shapes: &str) -> String {
+        format!(
+            "[[piece]]\nname = \"{name}\"\ncolor = [1, 2, 3]\nkicks = \"{kicks}\"\n\
+             shapes = {shapes}\n"
+        )
+    }
+    /// `pieces` with a kick table called `none` holding `kicks`.
+    fn set(kicks: &str, pieces: &[String]) -> Result<PieceSet, PieceSetError> {
+        format!("[kicks]\nnone = {kicks}\n{}", pieces.concat()).parse()
+    }
+    /// What is wrong with a piece whose rotations are `shapes`.
+    fn shape_error(shapes: &str) -> String {
+        match set(NO_KICKS, &[piece("P", "none", shapes)]) {
+            Err(PieceSetError::Shape { message, .. }) => message,
+            other => panic!("expected a shape error, got {other:?}"),
+        }
+    }
+
+    fn shape(rows: &[&str]) -> Array2D<bool> {
+        let rows = rows
+            .iter()
+            .map(|row| row.chars().map(|c| c == '#').collect())
+            .collect::<Vec<Vec<_>>>();
+        Array2D::from_rows(&rows).unwrap()
+    }
+
+    #[test]
+    fn a_valid_set_loads() {
+        let set = set(NO_KICKS, &[piece("P", "none", DOT)]).unwrap();
+        let p = Piece::from_char('P').unwrap();
+        assert_eq!(set.pieces(), vec![p]);
+        assert_eq!(set.color(p), (1, 2, 3));
+        assert_eq!(set.color(Piece::I), (192, 192, 192));
+    }
+
+    #[test]
+    fn malformed_names_are_refused() {
+        for name in ["PP", "G", "-", ""] {
+            let result = set(NO_KICKS, &[piece(name, "none", DOT)]);
+            assert!(matches!(result, Err(PieceSetError::Name(_))), "{name:?}");
+        }
+        let twice = [piece("P", "none", DOT), piece("P", "none", DOT)];
+        assert!(matches!(
+            set(NO_KICKS, &twice),
+            Err(PieceSetError::Duplicate('P'))
+        ));
+    }
+
+    #[test]
+    fn malformed_shapes_are_refused() {
+        assert!(shape_error(r###"[["#"]]"###).contains("4 rotations"));
+        assert!(shape_error(r###"[["##"], ["#"], ["#"], ["#"]]"###).contains("square"));
+        assert!(shape_error(r###"[[], ["#"], ["#"], ["#"]]"###).contains("square"));
+        assert!(shape_error(r###"[["."], ["#"], ["#"], ["#"]]"###).contains("one block"));
+        assert!(shape_error(r###"[["x"], ["#"], ["#"], ["#"]]"###).contains("unexpected"));
+        let sizes = r###"[["#"], ["##", ".#"], ["#"], ["#"]]"###;
+        assert!(shape_error(sizes).contains("different sizes"));
+    }
+
+    #[test]
+    fn malformed_kick_tables_are_refused() {
+        let dot = [piece("P", "none", DOT)];
+        let short = set("[[[0, 0]]]", &dot);
+        assert!(matches!(short, Err(PieceSetError::Kicks(_))));
+        let empty = set("[[], [], [], [], [], [], [], []]", &dot);
+        assert!(matches!(empty, Err(PieceSetError::Kicks(_))));
+        let unknown = set(NO_KICKS, &[piece("P", "srs", DOT)]);
+        assert!(matches!(unknown, Err(PieceSetError::UnknownKicks { .. })));
+    }
+
+    #[test]
+    fn malformed_files_are_refused() {
+        assert!(matches!(set(NO_KICKS, &[]), Err(PieceSetError::Empty)));
+        let toml = "[[piece]]\nname = 1".parse::<PieceSet>();
+        assert!(matches!(toml, Err(PieceSetError::Toml(_))));
+        let unknown_field = format!("{}size = 3\n", piece("P", "none", DOT));
+        assert!(matches!(
+            set(NO_KICKS, &[unknown_field]),
+            Err(PieceSetError::Toml(_))
+        ));
+        let missing = PieceSet::load("/nonexistent/pieces.toml");
+        assert!(matches!(missing, Err(PieceSetError::Io(_))));
+    }
+
+    #[test]
+    fn sets_load_from_files() {
+        let path = std::env::temp_dir().join(format!("tetris-pieces-{}.toml", std::process::id()));
+        fs::write(&path, TETROMINOES).unwrap();
+        let loaded = PieceSet::load(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.unwrap(), *PieceSet::tetrominoes());
+    }
+
+    #[test]
+    fn sets_round_trip_through_the_file_format() {
+        let set = PieceSet::tetrominoes();
+        assert_eq!(set.to_string().parse::<PieceSet>().unwrap(), *set);
+    }
+
+    #[test]
+    fn shipped_tetrominoes_are_srs() {
+        let set = PieceSet::tetrominoes();
+        assert_eq!(
+            set.pieces(),
+            [
+                Piece::I,
+                Piece::J,
+                Piece::L,
+                Piece::O,
+                Piece::S,
+                Piece::Z,
+                Piece::T
+            ]
+        );
+        let up = |piece| {
+            set.shape(Tetromino {
+                piece,
+                rotation: Rotation::Up,
+            })
+            .unwrap()
+            .clone()
+        };
+        assert_eq!(up(Piece::I), shape(&["....", "####", "....", "...."]));
+        assert_eq!(up(Piece::J), shape(&["#..", "###", "..."]));
+        assert_eq!(up(Piece::L), shape(&["..#", "###", "..."]));
+        assert_eq!(up(Piece::O), shape(&["##", "##"]));
+        assert_eq!(up(Piece::S), shape(&[".##", "##.", "..."]));
+        assert_eq!(up(Piece::Z), shape(&["##.", ".##", "..."]));
+        assert_eq!(up(Piece::T), shape(&[".#.", "###", "..."]));
+        let t = |rotation| {
+            set.shape(Tetromino {
+                piece: Piece::T,
+                rotation,
+            })
+            .unwrap()
+            .clone()
+        };
+        assert_eq!(t(Rotation::Right), shape(&[".#.", ".##", ".#."]));
+        assert_eq!(t(Rotation::Down), shape(&["...", "###", ".#."]));
+        assert_eq!(t(Rotation::Left), shape(&[".#.", "##.", ".#."]));
+        let i = |rotation| {
+            set.shape(Tetromino {
+                piece: Piece::I,
+                rotation,
+            })
+            .unwrap()
+            .clone()
+        };
+        assert_eq!(i(Rotation::Right), shape(&["..#.", "..#.", "..#.", "..#."]));
+        assert_eq!(i(Rotation::Left), shape(&[".#..", ".#..", ".#..", ".#.."]));
+
+        assert_eq!(
+            set.kicks(Piece::T, Rotation::Up, Rotation::Right),
+            [(0, 0), (-1, 0), (-1, 1), (0, -2), (-1, -2)]
+        );
+        assert_eq!(
+            set.kicks(Piece::S, Rotation::Left, Rotation::Up),
+            [(0, 0), (-1, 0), (-1, -1), (0, 2), (-1, 2)]
+        );
+        assert_eq!(
+            set.kicks(Piece::I, Rotation::Up, Rotation::Right),
+            [(0, 0), (-2, 0), (1, 0), (-2, -1), (1, 2)]
+        );
+        assert_eq!(
+            set.kicks(Piece::I, Rotation::Down, Rotation::Left),
+            [(0, 0), (2, 0), (-1, 0), (2, 1), (-1, -2)]
+        );
+        assert_eq!(set.kicks(Piece::O, Rotation::Up, Rotation::Right), [(0, 0)]);
+        assert_eq!(set.kicks(Piece::T, Rotation::Up, Rotation::Down), [(0, 0)]);
+    }
+}
@@ -0,0 +1,207 @@
+//! Piece randomizers. Each one deals pieces from the game's piece set using the game's
+//! seeded rng, so the same seed always deals the same pieces.
+use rand::{seq::SliceRandom, Rng, RngCore};
+use std::{collections::VecDeque, fmt, str::FromStr};
+use thiserror::Error;
+
+use crate::tetromino::Piece;
+
+/// Pieces the TGM randomizer remembers.
+pub const TGM_HISTORY: usize = 4;
+/// Times the TGM randomizer rolls again when it gets a piece from its history.
+pub const TGM_REROLLS: usize = 6;
+
+/// Decides which piece comes next.
+pub trait Randomizer: fmt::Debug + Send {
+    fn next(&mut self, rng: &mut dyn RngCore) -> Piece;
+    /// A copy of the randomizer in its current state, so games can be cloned.
+    fn box_clone(&self) -> Box<dyn Randomizer>;
+}
+impl Clone for Box<dyn Randomizer> {
+    fn clone(&self) -> Self {
+        self.box_clone()
+    }
+}
+
+/// Deals every piece `copies` times in a random order before refilling.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bag {
+    pieces: Vec<Piece>,
+    copies: usize,
+    bag: Vec<Piece>,
+}
+impl Bag {
+    pub fn new(pieces: Vec<Piece>, copies: usize) -> Self {
+        let bag = pieces.repeat(copies);
+        Bag {
+            pieces,
+            copies,
+            bag,
+        }
+    }
+}
+impl Randomizer for Bag {
+    fn next(&mut self, rng: &mut dyn RngCore) -> Piece {
+        if self.bag.is_empty() {
+            self.bag = self.pieces.repeat(self.copies);
+        }
+        let piece = *self.bag.choose(rng).expect("piece sets are never empty");
+        let index = self
+            .bag
+            .iter()
+            .position(|x| *x == piece)
+            .expect("the piece was just picked from the bag");
+        self.bag.remove(index);
+        piece
+    }
+    fn box_clone(&self) -> Box<dyn Randomizer> {
+        Box::new(self.clone())
+    }
+}
+
+/// Every piece is equally likely every time, repeats and droughts included.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Random {
+    pieces: Vec<Piece>,
+}
+impl Random {
+    pub fn new(pieces: Vec<Piece>) -> Self {
+        Random { pieces }
+    }
+}
+impl Randomizer for Random {
+    fn next(&mut self, rng: &mut dyn RngCore) -> Piece {
+        *self.pieces.choose(rng).expect("piece sets are never empty")
+    }
+    fn box_clone(&self) -> Box<dyn Randomizer> {
+        Box::new(self.clone())
+    }
+}
+
+/// The Tetris The Grand Master randomizer. Rolls once, then rerolls up to `rerolls`
+/// times while the piece is one of the last `slots` dealt, and keeps the last roll if
+/// none were new. With [`TGM_HISTORY`] and [`TGM_REROLLS`] this follows TGM2's
+/// randomizer, which also starts with S and Z in its history, but rolls once more: TGM2
+/// rolls 6 times in total, this rolls 7.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct History {
+    pieces: Vec<Piece>,
+    history: VecDeque<Piece>,
+    rerolls: usize,
+}
+impl History {
+    /// The history starts out as S and Z, like TGM, so games rarely open with one.
+    pub fn new(pieces: Vec<Piece>, slots: usize, rerolls: usize) -> Self {
+        let history = [Piece::Z, Piece::S]
+            .into_iter()
+            .cycle()
+            .take(slots)
+            .collect();
+        History {
+            pieces,
+            history,
+            rerolls,
+        }
+    }
+}
+impl Randomizer for History {
+    fn next(&mut self, rng: &mut dyn RngCore) -> Piece {
+        let mut piece = *self.pieces.choose(rng).expect("piece sets are never empty");
+        for _ in 0..self.rerolls {
+            if !self.history.contains(&piece) {
+                break;
+            }
+            piece = *self.pieces.choose(rng).expect("piece sets are never empty");
+        }
+        if !self.history.is_empty() {
+            self.history.pop_front();
+            self.history.push_back(piece);
+        }
+        piece
+    }
+    fn box_clone(&self) -> Box<dyn Randomizer> {
+        Box::new(self.clone())
+    }
+}
+
+/// The NES randomizer. Rolls one more than the number of pieces, and rolls again once,
+/// without the extra, if that gave the previous piece or the extra.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Nes {
+    pieces: Vec<Piece>,
+    last: Option<Piece>,
+}
+impl Nes {
+    pub fn new(pieces: Vec<Piece>) -> Self {
+        Nes { pieces, last: None }
+    }
+}
+impl Randomizer for Nes {
+    fn next(&mut self, rng: &mut dyn RngCore) -> Piece {
+        let roll = rng.gen_range(0..=self.pieces.len());
+        let piece = match self.pieces.get(roll) {
+            Some(piece) if self.last != Some(*piece) => *piece,
+            _ => self.pieces[rng.gen_range(0..self.pieces.len())],
+        };
+        self.last = Some(piece);
+        piece
+    }
+    fn box_clone(&self) -> Box<dyn Randomizer> {
+        Box::new(self.clone())
+    }
+}
+
+/// A randomizer to deal a game's pieces with, as chosen by its mode or the player.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum RandomizerKind {
+    /// Each piece once per bag, the guideline randomizer.
+    #[default]
+    SevenBag,
+    /// Each piece twice per bag.
+    FourteenBag,
+    Random,
+    /// The TGM history randomizer with [`TGM_HISTORY`] slots and [`TGM_REROLLS`] rerolls.
+    Tgm,
+    Nes,
+}
+impl RandomizerKind {
+    /// A fresh randomizer dealing `pieces`. The bags are named after the standard set's
+    /// 7 pieces, but hold each piece of whatever set is given once or twice.
+    pub fn build(self, pieces: Vec<Piece>) -> Box<dyn Randomizer> {
+        match self {
+            RandomizerKind::SevenBag => Box::new(Bag::new(pieces, 1)),
+            RandomizerKind::FourteenBag => Box::new(Bag::new(pieces, 2)),
+            RandomizerKind::Random => Box::new(Random::new(pieces)),
+            RandomizerKind::Tgm => Box::new(History::new(pieces, TGM_HISTORY, TGM_REROLLS)),
+            RandomizerKind::Nes => Box::new(Nes::new(pieces)),
+        }
+    }
+}
+/// Written as `7-bag`, `14-bag`, `random`, `tgm` or `nes`.
+impl fmt::Display for RandomizerKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            RandomizerKind::SevenBag => "7-bag",
+            RandomizerKind::FourteenBag => "14-bag",
+            RandomizerKind::Random => "random",
+            RandomizerKind::Tgm => "tgm",
+            RandomizerKind::Nes => "nes",
+        })
+    }
+}
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("unknown randomizer {0:?}")]
+pub struct ParseRandomizerError(pub String);
+impl FromStr for RandomizerKind {
+    type Err = ParseRandomizerError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "7-bag" => Ok(RandomizerKind::SevenBag),
+            "14-bag" => Ok(RandomizerKind::FourteenBag),
+            "random" => Ok(RandomizerKind::Random),
+            "tgm" => Ok(RandomizerKind::Tgm),
+            "nes" => Ok(RandomizerKind::Nes),
+            _ => Err(ParseRandomizerError(s.to_string())),
+        }
+    }
+}
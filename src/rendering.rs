@@ -1,15 +1,34 @@
-use crate::tetromino::Tetromino;
-use crate::{piece_is_legal, AppState, Rotation, COLS, ROWS};
+use tetris::{AppState, Cell, Loss, Piece, PieceSet, Rotation, Tetromino};
+use crate::highscores::HighScore;
 use femtovg::{Align, Baseline, Canvas, Color, Paint, Path, Renderer};
 use glutin::{context::PossiblyCurrentContext, surface::{Surface, WindowSurface, GlSurface}};
 use num::NumCast;
-use std::cmp::min_by;
+use std::{cmp::min_by, time::Duration};
 use winit::{window::Window, dpi::PhysicalSize};
 
-pub(crate) fn cell_size(size: PhysicalSize<u32>) -> f32 {
+/// Frames the perfect clear call-out stays on screen.
+const PERFECT_CLEAR_FRAMES: u64 = 120;
+
+pub(crate) fn piece_color(pieces: &PieceSet, piece: Piece) -> Color {
+    let (r, g, b) = pieces.color(piece);
+    Color::rgb(r, g, b)
+}
+pub(crate) fn cell_color(pieces: &PieceSet, cell: Cell) -> Color {
+    let (r, g, b) = cell.to_rgb(pieces);
+    Color::rgb(r, g, b)
+}
+
+/// The cell size that fits `boards` boards of up to `rows` by `cols` cells side by side.
+pub(crate) fn cell_size(size: PhysicalSize<u32>, boards: usize, rows: usize, cols: usize) -> f32 {
+    // Boards side by side also need room for their held piece and queue
+    let width = if boards > 1 {
+        size.width as f32 / boards as f32 / (cols + 12) as f32
+    } else {
+        size.width as f32 / (cols + 1) as f32
+    };
     min_by(
-        size.height as f32 / (ROWS + 1) as f32,
-        size.width as f32 / (COLS + 1) as f32,
+        size.height as f32 / (rows + 1) as f32,
+        width,
         |x, y| x.partial_cmp(y).expect("NaN in cell size calculation"),
     ) * 0.95
 }
@@ -34,44 +53,115 @@ pub(crate) struct BoardInfo {
     board_top: f32,
     line_width: f32,
     font_size: f32,
+    /// Left edge of the part of the window this board gets.
+    area_left: f32,
+    /// Size of the part of the window this board gets.
     board_size: PhysicalSize<u32>,
+    rows: usize,
+    cols: usize,
 }
-pub(crate) fn board_location(size: PhysicalSize<u32>, cell_size: f32) -> (f32, f32) {
-    let board_left = (size.width as f32 - cell_size * COLS as f32) / 2.0;
-    let board_top = (size.height as f32 - cell_size * ROWS as f32) / 1.5;
+pub(crate) fn board_location(size: PhysicalSize<u32>, cell_size: f32, area_left: f32, rows: usize, cols: usize) -> (f32, f32) {
+    let board_left = area_left + (size.width as f32 - cell_size * cols as f32) / 2.0;
+    let board_top = (size.height as f32 - cell_size * rows as f32) / 1.5;
     (board_left, board_top)
 }
+/// Everything on screen that isn't part of the game state.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct Hud {
+    /// Text shown under the board.
+    pub(crate) status: Option<String>,
+    pub(crate) paused: bool,
+    /// The table shown once the game is over.
+    pub(crate) high_scores: Vec<HighScore>,
+    /// Where the game that just ended landed in `high_scores`.
+    pub(crate) new_high_score: Option<usize>,
+    /// How a match ended for this board. Shows the game over box in its place even if
+    /// this board is still alive.
+    pub(crate) verdict: Option<String>,
+    /// Real time the game has taken, to the millisecond. Timed modes fall back to the
+    /// game's own frame clock without it.
+    pub(crate) time: Option<Duration>,
+    /// Names of the keys that pause and restart, for the help text. Missing if the
+    /// session can't be paused or restarted from the keyboard.
+    pub(crate) pause_keys: Option<String>,
+    pub(crate) restart_keys: Option<String>,
+}
+
 pub(crate) fn render<T: Renderer>(
     context: &PossiblyCurrentContext,
     surface: &Surface<WindowSurface>,
     window: &Window,
     canvas: &mut Canvas<T>,
-    state: &mut AppState,
+    boards: Vec<(&AppState, Hud)>,
 ) {
     let size = window.inner_size();
-    let cell_size = cell_size(size);
-    let (board_left, board_top) = board_location(size, cell_size);
+    // Every board is drawn at the same scale, so the biggest one decides it
+    let rows = boards.iter().map(|(state, _)| state.height()).max().unwrap_or_default();
+    let cols = boards.iter().map(|(state, _)| state.width()).max().unwrap_or_default();
+    let cell_size = cell_size(size, boards.len(), rows, cols);
     let line_width = cell_size / 20.0;
-    let board_info = BoardInfo {
-        cell_size,
-        board_left,
-        board_top,
-        line_width,
-        font_size: cell_size,
-        board_size: size,
-    };
+    // Each board gets an equal slice of the window, left to right
+    let area_size = PhysicalSize::new(size.width / boards.len().max(1) as u32, size.height);
 
     canvas.set_size(size.width, size.height, window.scale_factor() as f32);
     canvas.clear_rect(0, 0, size.width, size.height, Color::black());
 
+    for (index, (state, hud)) in boards.into_iter().enumerate() {
+        let area_left = (area_size.width as usize * index) as f32;
+        let (rows, cols) = (state.height(), state.width());
+        let (board_left, board_top) = board_location(area_size, cell_size, area_left, rows, cols);
+        let board_info = BoardInfo {
+            cell_size,
+            board_left,
+            board_top,
+            line_width,
+            font_size: cell_size,
+            area_left,
+            board_size: area_size,
+            rows,
+            cols,
+        };
+
+        if hud.paused {
+            // Hide the field so pausing can't be used to study it
+            draw_grid(board_info, canvas);
+            draw_paused(board_info, &hud, canvas);
+        } else {
+            draw_field(state, &hud, board_info, canvas);
+        }
+
+        if let Some(status) = hud.status {
+            draw_status_text(board_info, status, canvas);
+        }
+    }
+
+    // Display to screen
+    canvas.flush();
+    surface
+        .swap_buffers(context)
+        .expect("Could not swap buffers");
+}
+
+fn draw_field<T: Renderer>(
+    state: &AppState,
+    hud: &Hud,
+    board_info: BoardInfo,
+    canvas: &mut Canvas<T>,
+) {
+    let BoardInfo { line_width, .. } = board_info;
+
     // Draw Board
     draw_board(state, board_info, canvas);
 
+    // The board's rows count the hidden buffer, the screen's don't
+    let buffer = state.buffer() as isize;
+
     // Draw Piece
-    let paint = Paint::color(state.piece.to_color());
+    let paint = Paint::color(piece_color(&state.config.pieces, state.piece.piece));
     draw_piece(
+        &state.config.pieces,
         state.piece,
-        state.location,
+        (state.location.0, state.location.1 - buffer),
         board_info,
         canvas,
         &paint,
@@ -79,11 +169,12 @@ pub(crate) fn render<T: Renderer>(
     );
 
     // Draw Ghost Piece
-    let paint = &Paint::color(state.piece.to_color()).with_line_width(line_width * 2.0);
-    let row = ghost_location(state);
+    let paint = &Paint::color(piece_color(&state.config.pieces, state.piece.piece)).with_line_width(line_width * 2.0);
+    let row = state.ghost_location();
     draw_piece(
+        &state.config.pieces,
         state.piece,
-        (state.location.0, row),
+        (state.location.0, row - buffer),
         board_info,
         canvas,
         paint,
@@ -91,8 +182,9 @@ pub(crate) fn render<T: Renderer>(
     );
     // Draw Held Piece
     if let Some(held) = state.held {
-        let paint = Paint::color(held.to_color());
+        let paint = Paint::color(piece_color(&state.config.pieces, held));
         draw_piece(
+            &state.config.pieces,
             Tetromino {
                 piece: held,
                 rotation: Rotation::Up,
@@ -106,62 +198,176 @@ pub(crate) fn render<T: Renderer>(
         draw_held_text(board_info, canvas);
     }
 
+    // Draw Next Pieces
+    draw_queue(state, board_info, canvas);
+
     draw_grid(board_info, canvas);
+    draw_garbage_meter(state, board_info, canvas);
 
-    if state.game_over {
-        draw_game_over(board_info, canvas, state);
+    if state.game_over || hud.verdict.is_some() {
+        draw_game_over(board_info, canvas, state, hud);
     } else {
         draw_score_text(board_info, state, canvas);
+        draw_level_text(board_info, state, canvas);
+        draw_combo_text(board_info, state, canvas);
+        draw_goal_text(board_info, state, hud, canvas);
+        draw_perfect_clear(board_info, state, canvas);
     }
+}
 
-    // Display to screen
-    canvas.flush();
-    surface
-        .swap_buffers(context)
-        .expect("Could not swap buffers");
+/// `m:ss.mmm`, for timed modes.
+fn format_time(time: Duration) -> String {
+    let millis = time.as_millis();
+    format!("{}:{:02}.{:03}", millis / 60_000, millis / 1000 % 60, millis % 1000)
+}
+/// How long the game has taken, on the real clock if the session keeps one.
+fn elapsed(state: &AppState, hud: &Hud) -> Duration {
+    hud.time.unwrap_or_else(|| state.elapsed())
 }
 
 fn draw_game_over<T: Renderer>(
     board_info: BoardInfo,
     canvas: &mut Canvas<T>,
-    state: &mut AppState,
+    state: &AppState,
+    hud: &Hud,
 ) {
     let size = board_info.board_size;
     let (width, height) = (size.width as f32, size.height as f32);
+    let left = board_info.area_left;
     let mut path = Path::new();
-    path.rect(width / 3.0, height / 3.0, width / 3.0, height / 3.0);
+    path.rect(left + width / 6.0, height / 8.0, width * 2.0 / 3.0, height * 3.0 / 4.0);
 
     canvas.fill_path(&path, &Paint::color(Color::black()));
     canvas.stroke_path(&path, &Paint::color(Color::white()));
 
+    let font_size = board_info.font_size;
     let mut paint = Paint::color(Color::white())
-        .with_font_size(board_info.font_size)
+        .with_font_size(font_size)
         .with_text_align(Align::Center)
-        .with_text_baseline(Baseline::Bottom);
-
-    let score = format!("Score: {}", state.score);
+        .with_text_baseline(Baseline::Top);
+    let mut y = height / 8.0 + font_size / 2.0;
 
+    let title = match (&hud.verdict, state.time_left()) {
+        (Some(verdict), _) => verdict.as_str(),
+        (None, Some(Duration::ZERO)) => "Time's Up",
+        (None, _) if state.completed => "Complete!",
+        (None, _) => match state.loss {
+            Some(Loss::BlockOut) => "Block Out",
+            Some(Loss::LockOut) => "Lock Out",
+            Some(Loss::TopOut) => "Top Out",
+            None => "Game Over",
+        },
+    };
     canvas
-        .fill_text(width / 2.0, height / 2.0, "Game Over", &paint)
+        .fill_text(left + width / 2.0, y, title, &paint)
         .expect("Unable to display game over top text");
-    
-    paint.set_text_baseline(Baseline::Top);
+    y += font_size * 1.25;
 
+    let mode = state.config.mode;
+    let score = if mode.ranked_by_time() {
+        format!("Time: {}    Seed: {}", format_time(elapsed(state, hud)), state.seed)
+    } else {
+        format!("Score: {}    Seed: {}", state.score, state.seed)
+    };
+    paint.set_font_size(font_size * 0.75);
     canvas
-        .fill_text(width / 2.0, height / 2.0, score, &paint)
+        .fill_text(left + width / 2.0, y, score, &paint)
         .expect("Unable to display game over score text");
-}
-fn ghost_location(state: &mut AppState) -> isize {
-    let location = state.location;
-    while piece_is_legal(state) {
-        state.location.1 += 1;
+    y += font_size;
+
+    if state.perfect_clears > 0 {
+        canvas
+            .fill_text(left + width / 2.0, y, format!("Perfect Clears: {}", state.perfect_clears), &paint)
+            .expect("Unable to display perfect clears");
+        y += font_size;
+    }
+    y += font_size * 0.25;
+
+    if !hud.high_scores.is_empty() {
+        canvas
+            .fill_text(left + width / 2.0, y, "High Scores", &paint)
+            .expect("Unable to display high score title");
+        y += font_size;
+
+        paint.set_font_size(font_size * 0.6);
+        for (rank, entry) in hud.high_scores.iter().enumerate() {
+            let color = if hud.new_high_score == Some(rank) {
+                Color::rgb(255, 255, 0)
+            } else {
+                Color::white()
+            };
+            paint.set_color(color);
+            let text = if mode.ranked_by_time() {
+                format!(
+                    "{}. {}  {}  {}",
+                    rank + 1,
+                    entry.name,
+                    format_time(entry.duration()),
+                    entry.date_string(),
+                )
+            } else {
+                let duration = entry.duration().as_secs();
+                format!(
+                    "{}. {}  {}  {} lines  level {}  {}:{:02}  {}",
+                    rank + 1,
+                    entry.name,
+                    entry.score,
+                    entry.lines,
+                    entry.level,
+                    duration / 60,
+                    duration % 60,
+                    entry.date_string(),
+                )
+            };
+            canvas
+                .fill_text(left + width / 2.0, y, text, &paint)
+                .expect("Unable to display high score");
+            y += font_size * 0.75;
+        }
+        paint.set_color(Color::white());
+    }
+
+    if let Some(restart) = &hud.restart_keys {
+        paint.set_font_size(font_size * 0.75);
+        paint.set_text_baseline(Baseline::Bottom);
+        canvas
+            .fill_text(left + width / 2.0, height * 7.0 / 8.0 - font_size / 4.0, format!("Press {restart} to restart"), &paint)
+            .expect("Unable to display game over restart text");
     }
-    let ret = state.location.1 - 1;
-    state.location = location;
-    ret
 }
+fn draw_paused<T: Renderer>(board_info: BoardInfo, hud: &Hud, canvas: &mut Canvas<T>) {
+    let size = board_info.board_size;
+    let (width, height) = (size.width as f32, size.height as f32);
+    let left = board_info.area_left;
+    let mut path = Path::new();
+    path.rect(left + width / 3.0, height / 3.0, width / 3.0, height / 3.0);
+
+    canvas.fill_path(&path, &Paint::color(Color::black()));
+    canvas.stroke_path(&path, &Paint::color(Color::white()));
+
+    let mut paint = Paint::color(Color::white())
+        .with_font_size(board_info.font_size)
+        .with_text_align(Align::Center)
+        .with_text_baseline(Baseline::Bottom);
+
+    canvas
+        .fill_text(left + width / 2.0, height / 2.0, "Paused", &paint)
+        .expect("Unable to display paused text");
+
+    paint.set_text_baseline(Baseline::Top);
+    paint.set_font_size(board_info.font_size * 0.75);
 
-fn draw_board<T: Renderer>(state: &mut AppState, board_info: BoardInfo, canvas: &mut Canvas<T>) {
+    let help = match (&hud.pause_keys, &hud.restart_keys) {
+        (Some(pause), Some(restart)) => format!("{pause} to resume, {restart} to restart"),
+        (Some(pause), None) => format!("{pause} to resume"),
+        (None, Some(restart)) => format!("{restart} to restart"),
+        (None, None) => return,
+    };
+    canvas
+        .fill_text(left + width / 2.0, height / 2.0, help, &paint)
+        .expect("Unable to display paused help text");
+}
+fn draw_board<T: Renderer>(state: &AppState, board_info: BoardInfo, canvas: &mut Canvas<T>) {
     let BoardInfo {
         cell_size,
         line_width,
@@ -170,9 +376,10 @@ fn draw_board<T: Renderer>(state: &mut AppState, board_info: BoardInfo, canvas:
     state
         .board
         .enumerate_row_major()
+        .filter(|((row, _), _)| *row >= state.buffer())
         .for_each(|((row, col), el)| {
-            if let Some(color) = el {
-                let (x, y) = index_to_grid(row, col, board_info);
+            if let Some(cell) = el {
+                let (x, y) = index_to_grid(row - state.buffer(), col, board_info);
                 let mut path = Path::new();
                 path.rect(
                     x + line_width / 2.0,
@@ -180,13 +387,13 @@ fn draw_board<T: Renderer>(state: &mut AppState, board_info: BoardInfo, canvas:
                     cell_size - line_width,
                     cell_size - line_width,
                 );
-                canvas.fill_path(&path, &Paint::color(*color));
+                canvas.fill_path(&path, &Paint::color(cell_color(&state.config.pieces, *cell)));
             }
         });
 }
 
-fn draw_score_text<T: Renderer>(board_info: BoardInfo, state: &mut AppState, canvas: &mut Canvas<T>) {
-    let (a, b) = index_to_grid(0, COLS / 2, board_info);
+fn draw_score_text<T: Renderer>(board_info: BoardInfo, state: &AppState, canvas: &mut Canvas<T>) {
+    let (a, b) = index_to_grid(0, board_info.cols / 2, board_info);
     let paint = &Paint::color(Color::white())
         .with_font_size(board_info.font_size)
         .with_text_align(Align::Center)
@@ -197,6 +404,138 @@ fn draw_score_text<T: Renderer>(board_info: BoardInfo, state: &mut AppState, can
         .fill_text(a, b, text, paint)
         .expect("Could not display score");
 }
+fn draw_combo_text<T: Renderer>(board_info: BoardInfo, state: &AppState, canvas: &mut Canvas<T>) {
+    let (a, b) = index_to_grid(8, -3, board_info);
+    let paint = &Paint::color(Color::white())
+        .with_font_size(board_info.font_size)
+        .with_text_align(Align::Center)
+        .with_text_baseline(Baseline::Top);
+
+    if let Some(combo @ 1..) = state.combo {
+        canvas
+            .fill_text(a, b, format!("Combo: {}", combo), paint)
+            .expect("Could not display combo");
+    }
+    if state.back_to_back {
+        canvas
+            .fill_text(a, b + board_info.cell_size, "Back-to-Back", paint)
+            .expect("Could not display back-to-back");
+    }
+}
+fn draw_level_text<T: Renderer>(board_info: BoardInfo, state: &AppState, canvas: &mut Canvas<T>) {
+    let (a, b) = index_to_grid(5, -3, board_info);
+    let paint = &Paint::color(Color::white())
+        .with_font_size(board_info.font_size)
+        .with_text_align(Align::Center)
+        .with_text_baseline(Baseline::Top);
+
+    canvas
+        .fill_text(a, b, format!("Level: {}", state.level), paint)
+        .expect("Could not display level");
+    canvas
+        .fill_text(a, b + board_info.cell_size, format!("Lines: {}", state.lines), paint)
+        .expect("Could not display lines");
+}
+fn draw_goal_text<T: Renderer>(
+    board_info: BoardInfo,
+    state: &AppState,
+    hud: &Hud,
+    canvas: &mut Canvas<T>,
+) {
+    let (a, b) = index_to_grid(11, -3, board_info);
+    let paint = &Paint::color(Color::white())
+        .with_font_size(board_info.font_size)
+        .with_text_align(Align::Center)
+        .with_text_baseline(Baseline::Top);
+
+    if let Some(time_left) = state.time_left() {
+        canvas
+            .fill_text(a, b, format_time(time_left), paint)
+            .expect("Could not display countdown");
+    }
+    if let Some(lines_left) = state.lines_left() {
+        canvas
+            .fill_text(a, b, format_time(elapsed(state, hud)), paint)
+            .expect("Could not display timer");
+        canvas
+            .fill_text(a, b + board_info.cell_size, format!("Left: {}", lines_left), paint)
+            .expect("Could not display lines left");
+    }
+}
+/// Announces a perfect clear over the middle of the board for a couple of seconds.
+fn draw_perfect_clear<T: Renderer>(board_info: BoardInfo, state: &AppState, canvas: &mut Canvas<T>) {
+    let Some(frame) = state.last_perfect_clear else {
+        return;
+    };
+    if state.frame - frame >= PERFECT_CLEAR_FRAMES {
+        return;
+    }
+    let (a, b) = index_to_grid(board_info.rows / 2, board_info.cols / 2, board_info);
+    let paint = &Paint::color(Color::rgb(255, 255, 0))
+        .with_font_size(board_info.font_size * 1.5)
+        .with_text_align(Align::Center)
+        .with_text_baseline(Baseline::Middle);
+
+    canvas
+        .fill_text(a, b, "PERFECT CLEAR", paint)
+        .expect("Could not display perfect clear");
+}
+/// A bar left of the board as tall as the garbage waiting to rise.
+fn draw_garbage_meter<T: Renderer>(state: &AppState, board_info: BoardInfo, canvas: &mut Canvas<T>) {
+    if state.pending_garbage == 0 {
+        return;
+    }
+    let rows = state.pending_garbage.min(board_info.rows as u32) as usize;
+    let (x, y) = index_to_grid(board_info.rows - rows, 0, board_info);
+    let mut path = Path::new();
+    path.rect(
+        x - board_info.cell_size / 3.0,
+        y,
+        board_info.cell_size / 4.0,
+        board_info.cell_size * rows as f32,
+    );
+    canvas.fill_path(&path, &Paint::color(Color::rgb(255, 0, 0)));
+}
+fn draw_queue<T: Renderer>(state: &AppState, board_info: BoardInfo, canvas: &mut Canvas<T>) {
+    if state.queue.is_empty() {
+        return;
+    }
+    for (index, piece) in state.queue.iter().enumerate() {
+        let paint = Paint::color(piece_color(&state.config.pieces, *piece));
+        draw_piece(
+            &state.config.pieces,
+            Tetromino {
+                piece: *piece,
+                rotation: Rotation::Up,
+            },
+            (board_info.cols as isize + 2, index as isize * 3),
+            board_info,
+            canvas,
+            &paint,
+            PieceType::Preview,
+        );
+    }
+    let (a, b) = index_to_grid(0, board_info.cols as isize + 4, board_info);
+    let paint = &Paint::color(Color::white())
+        .with_font_size(board_info.font_size)
+        .with_text_align(Align::Center)
+        .with_text_baseline(Baseline::Bottom);
+
+    canvas
+        .fill_text(a, b, "Next:", paint)
+        .expect("Could not display next pieces");
+}
+fn draw_status_text<T: Renderer>(board_info: BoardInfo, status: String, canvas: &mut Canvas<T>) {
+    let (a, b) = index_to_grid(board_info.rows, board_info.cols / 2, board_info);
+    let paint = &Paint::color(Color::white())
+        .with_font_size(board_info.font_size * 0.75)
+        .with_text_align(Align::Center)
+        .with_text_baseline(Baseline::Top);
+
+    canvas
+        .fill_text(a, b, status, paint)
+        .expect("Could not display status");
+}
 fn draw_held_text<T: Renderer>(board_info: BoardInfo, canvas: &mut Canvas<T>) {
     let (a, b) = index_to_grid(0, -3, board_info);
     let paint = &Paint::color(Color::white())
@@ -211,20 +550,20 @@ fn draw_held_text<T: Renderer>(board_info: BoardInfo, canvas: &mut Canvas<T>) {
 
 
 fn draw_grid<T: Renderer>(board_info: BoardInfo, canvas: &mut Canvas<T>) {
-    let BoardInfo { cell_size, .. } = board_info;
+    let BoardInfo { cell_size, rows, cols, .. } = board_info;
     let line_paint = Paint::color(Color::rgb(127, 127, 127)).with_line_width(cell_size / 20.0);
-    for x in 0..=ROWS {
+    for x in 0..=rows {
         let mut path = Path::new();
         let (a, b) = index_to_grid(x, 0, board_info);
-        let (c, d) = index_to_grid(x, COLS, board_info);
+        let (c, d) = index_to_grid(x, cols, board_info);
         path.move_to(a, b);
         path.line_to(c, d);
         canvas.stroke_path(&path, &line_paint);
     }
-    for x in 0..=COLS {
+    for x in 0..=cols {
         let mut path = Path::new();
         let (a, b) = index_to_grid(0, x, board_info);
-        let (c, d) = index_to_grid(ROWS, x, board_info);
+        let (c, d) = index_to_grid(rows, x, board_info);
         path.move_to(a, b);
         path.line_to(c, d);
         canvas.stroke_path(&path, &line_paint);
@@ -234,9 +573,11 @@ fn draw_grid<T: Renderer>(board_info: BoardInfo, canvas: &mut Canvas<T>) {
 enum PieceType {
     Normal,
     Held,
+    Preview,
     Ghost,
 }
 fn draw_piece<T: Renderer>(
+    pieces: &PieceSet,
     piece: Tetromino,
     location: (isize, isize),
     board_info: BoardInfo,
@@ -250,12 +591,15 @@ fn draw_piece<T: Renderer>(
         ..
     } = board_info;
     let line_width = match piece_type {
-        PieceType::Held => line_width / 2.0,
+        PieceType::Held | PieceType::Preview => line_width / 2.0,
         _ => line_width,
     };
     let (piece_col, piece_row) = location;
-    piece
-        .to_blocks()
+    // Pieces from another set, like an opponent's, have no shape to draw
+    let Some(blocks) = pieces.shape(piece) else {
+        return;
+    };
+    blocks
         .enumerate_row_major()
         .filter_map(|((row, col), x)| match *x {
             true => Some((row as isize + piece_row, col as isize + piece_col)),
@@ -263,7 +607,7 @@ fn draw_piece<T: Renderer>(
         })
         .for_each(|(row, col)| {
             let mut path = Path::new();
-            if row < 0 || row >= ROWS as isize {
+            if row < 0 || row >= board_info.rows as isize {
                 return;
             }
             let (x, y) = index_to_grid(row, col, board_info);
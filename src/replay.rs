@@ -0,0 +1,452 @@
+//! Recorded games. A replay is the randomizer seed plus every input tagged with the frame
+//! it was applied on. The engine is deterministic, so feeding the inputs back in between
+//! the same gravity ticks reproduces the game exactly.
+//!
+//! On disk a replay is a small text file:
+//!
+//! ```text
+//! tetris-replay 8
+//! seed 1234
+//! level 1
+//! mode cheese-18
+//! randomizer 7-bag
+//! hole-change 0.3
+//! cheese-height 10
+//! gravity 0.016666668
+//! board 10x20
+//! buffer 20
+//! input 0 move-left
+//! input 12 rotate-right
+//! end 3600
+//! ```
+//!
+//! `input <frame> <action>` means the action was applied after `frame` gravity ticks.
+//! `end <frames>` is how many ticks the game ran for in total. `board` is the width and
+//! height of the visible field and `buffer` the hidden rows above it. `gravity` is the
+//! cells per frame at each level. Missing lines take the engine defaults, except a
+//! missing `randomizer` or `gravity`, which are the mode's. Replays from before version 4
+//! spawned pieces differently, so they can't be played back any more. Version 4 had no
+//! `randomizer` line, and every mode used the 7-bag. Before version 8 there was no
+//! `gravity` line, and every mode used the guideline curve.
+//!
+//! Games played with a custom piece set store the whole set, as a `pieces <lines>` line
+//! followed by that many lines of the set's file. Without one, or before version 6, the
+//! standard tetrominoes are used.
+use std::{fmt, fs, path::Path, str::FromStr, sync::Arc, time::Duration};
+use thiserror::Error;
+
+use crate::game::{guideline_gravity, AppState, Config, Event, StateChange, FRAME};
+use crate::mode::Mode;
+use crate::pieces::PieceSet;
+use crate::randomizer::RandomizerKind;
+
+pub const REPLAY_VERSION: u32 = 8;
+/// The oldest replays that still play back the same.
+const OLDEST_VERSION: u32 = 4;
+const MAGIC: &str = "tetris-replay";
+
+#[derive(Debug, Error)]
+pub enum ReplayError {
+    #[error("could not access replay file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("not a replay file")]
+    NotAReplay,
+    #[error("replay version {0} is not supported, expected at most {REPLAY_VERSION}")]
+    Version(u32),
+    #[error("replay version {0} was recorded with older rules and can't be played back")]
+    Outdated(u32),
+    #[error("line {line}: {message}")]
+    Parse { line: usize, message: String },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Replay {
+    pub seed: u64,
+    pub start_level: u32,
+    pub mode: Mode,
+    pub randomizer: RandomizerKind,
+    /// See [`Config::hole_change`].
+    pub hole_change: f64,
+    /// See [`Config::cheese_height`].
+    pub cheese_height: usize,
+    /// See [`Config::gravity`].
+    pub gravity: Vec<f32>,
+    pub width: usize,
+    pub height: usize,
+    pub buffer: usize,
+    pub pieces: Arc<PieceSet>,
+    /// Inputs in the order they were applied, with the frame they were applied on.
+    pub inputs: Vec<(u64, StateChange)>,
+    /// Gravity ticks the game ran for.
+    pub frames: u64,
+}
+impl Replay {
+    /// An empty recording of the game `state` is about to play.
+    pub fn for_game(state: &AppState) -> Self {
+        Replay {
+            seed: state.seed,
+            start_level: state.config.start_level,
+            mode: state.config.mode,
+            randomizer: state.config.randomizer,
+            hole_change: state.config.hole_change,
+            cheese_height: state.config.cheese_height,
+            gravity: state.config.gravity.clone(),
+            width: state.config.width,
+            height: state.config.height,
+            buffer: state.config.buffer,
+            pieces: state.config.pieces.clone(),
+            inputs: Vec::new(),
+            frames: state.frame,
+        }
+    }
+    pub fn record(&mut self, frame: u64, change: StateChange) {
+        self.inputs.push((frame, change));
+        self.frames = self.frames.max(frame);
+    }
+    pub fn duration(&self) -> Duration {
+        FRAME * self.frames as u32
+    }
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ReplayError> {
+        fs::read_to_string(path)?.parse()
+    }
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), ReplayError> {
+        fs::write(path, self.to_string())?;
+        Ok(())
+    }
+    /// A fresh game set up the way the recorded one started.
+    pub fn new_game(&self) -> AppState {
+        let config = Config {
+            start_level: self.start_level,
+            mode: self.mode,
+            randomizer: self.randomizer,
+            hole_change: self.hole_change,
+            cheese_height: self.cheese_height,
+            gravity: self.gravity.clone(),
+            width: self.width,
+            height: self.height,
+            buffer: self.buffer,
+            pieces: self.pieces.clone(),
+            ..Default::default()
+        };
+        AppState::from_seed(config, self.seed)
+    }
+}
+impl fmt::Display for Replay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{MAGIC} {REPLAY_VERSION}")?;
+        writeln!(f, "seed {}", self.seed)?;
+        writeln!(f, "level {}", self.start_level)?;
+        writeln!(f, "mode {}", self.mode)?;
+        writeln!(f, "randomizer {}", self.randomizer)?;
+        writeln!(f, "hole-change {}", self.hole_change)?;
+        writeln!(f, "cheese-height {}", self.cheese_height)?;
+        write!(f, "gravity")?;
+        for gravity in &self.gravity {
+            write!(f, " {gravity}")?;
+        }
+        writeln!(f)?;
+        writeln!(f, "board {}x{}", self.width, self.height)?;
+        writeln!(f, "buffer {}", self.buffer)?;
+        if *self.pieces != *PieceSet::tetrominoes() {
+            let pieces = self.pieces.to_string();
+            writeln!(f, "pieces {}", pieces.lines().count())?;
+            f.write_str(&pieces)?;
+        }
+        for (frame, change) in &self.inputs {
+            writeln!(f, "input {frame} {change}")?;
+        }
+        writeln!(f, "end {}", self.frames)
+    }
+}
+impl FromStr for Replay {
+    type Err = ReplayError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut lines = s.lines().enumerate();
+        let header = lines.next().map(|(_, line)| line).unwrap_or_default();
+        let version = header
+            .strip_prefix(MAGIC)
+            .and_then(|version| version.trim().parse().ok())
+            .ok_or(ReplayError::NotAReplay)?;
+        if version > REPLAY_VERSION {
+            return Err(ReplayError::Version(version));
+        }
+        if version < OLDEST_VERSION {
+            return Err(ReplayError::Outdated(version));
+        }
+        let defaults = Config::default();
+        let mut replay = Replay {
+            seed: 0,
+            start_level: 1,
+            mode: Mode::Marathon,
+            randomizer: defaults.randomizer,
+            hole_change: defaults.hole_change,
+            cheese_height: defaults.cheese_height,
+            gravity: Vec::new(),
+            width: defaults.width,
+            height: defaults.height,
+            buffer: defaults.buffer,
+            pieces: defaults.pieces,
+            inputs: Vec::new(),
+            frames: 0,
+        };
+        let mut seed = None;
+        let mut randomizer = None;
+        let mut gravity = None;
+        while let Some((index, line)) = lines.next() {
+            let parse_error = |message: &str| ReplayError::Parse {
+                line: index + 1,
+                message: message.to_string(),
+            };
+            let fields = line.split_whitespace().collect::<Vec<_>>();
+            match fields.as_slice() {
+                [] => {}
+                ["seed", value] => {
+                    seed = Some(value.parse().map_err(|_| parse_error("invalid seed"))?);
+                }
+                ["level", value] => {
+                    replay.start_level = value.parse().map_err(|_| parse_error("invalid level"))?;
+                }
+                ["mode", mode] => {
+                    replay.mode = mode.parse().map_err(|err| parse_error(&format!("{err}")))?;
+                }
+                ["randomizer", value] => {
+                    randomizer = Some(
+                        value
+                            .parse()
+                            .map_err(|err| parse_error(&format!("{err}")))?,
+                    );
+                }
+                ["hole-change", value] => {
+                    replay.hole_change = value
+                        .parse()
+                        .map_err(|_| parse_error("invalid hole change"))?;
+                }
+                ["cheese-height", value] => {
+                    replay.cheese_height = value
+                        .parse()
+                        .ok()
+                        .filter(|rows| *rows >= 1)
+                        .ok_or_else(|| parse_error("invalid cheese height"))?;
+                }
+                ["gravity", table @ ..] if !table.is_empty() => {
+                    let table = table
+                        .iter()
+                        .map(|value| value.parse().ok().filter(|gravity: &f32| *gravity >= 0.0))
+                        .collect::<Option<Vec<_>>>()
+                        .ok_or_else(|| parse_error("invalid gravity"))?;
+                    gravity = Some(table);
+                }
+                ["board", size] => {
+                    let (width, height) = size
+                        .split_once('x')
+                        .and_then(|(width, height)| {
+                            Some((width.parse().ok()?, height.parse().ok()?))
+                        })
+                        .filter(|(width, height)| *width >= 4 && *height >= 4)
+                        .ok_or_else(|| parse_error("invalid board size"))?;
+                    replay.width = width;
+                    replay.height = height;
+                }
+                ["pieces", count] => {
+                    let count = count
+                        .parse()
+                        .map_err(|_| parse_error("invalid piece set length"))?;
+                    let set = lines
+                        .by_ref()
+                        .take(count)
+                        .map(|(_, line)| format!("{line}\n"))
+                        .collect::<String>();
+                    let set = set.parse().map_err(|err| parse_error(&format!("{err}")))?;
+                    replay.pieces = Arc::new(set);
+                }
+                ["buffer", value] => {
+                    replay.buffer = value
+                        .parse()
+                        .ok()
+                        .filter(|buffer| *buffer >= 2)
+                        .ok_or_else(|| parse_error("invalid buffer"))?;
+                }
+                ["input", frame, change] => {
+                    let frame = frame.parse().map_err(|_| parse_error("invalid frame"))?;
+                    if replay.inputs.last().is_some_and(|(last, _)| *last > frame) {
+                        return Err(parse_error("inputs are out of order"));
+                    }
+                    let change = change
+                        .parse()
+                        .map_err(|err| parse_error(&format!("{err}")))?;
+                    replay.inputs.push((frame, change));
+                }
+                ["end", frames] => {
+                    replay.frames = frames.parse().map_err(|_| parse_error("invalid frame"))?;
+                }
+                _ => return Err(parse_error("unrecognised line")),
+            }
+        }
+        replay.seed = seed.ok_or(ReplayError::Parse {
+            line: 1,
+            message: "missing seed".to_string(),
+        })?;
+        replay.randomizer = match (randomizer, version) {
+            (Some(randomizer), _) => randomizer,
+            (None, 4) => RandomizerKind::SevenBag,
+            (None, _) => replay.mode.randomizer(),
+        };
+        replay.gravity = match gravity {
+            Some(gravity) => gravity,
+            None if version < 8 => guideline_gravity(),
+            None => replay.mode.gravity(),
+        };
+        Ok(replay)
+    }
+}
+
+/// Plays a [`Replay`] back through a fresh [`AppState`] one frame at a time.
+#[derive(Debug, Clone)]
+pub struct Playback {
+    pub replay: Replay,
+    pub state: AppState,
+    next_input: usize,
+}
+impl Playback {
+    pub fn new(replay: Replay) -> Self {
+        Playback {
+            state: replay.new_game(),
+            replay,
+            next_input: 0,
+        }
+    }
+    pub fn is_finished(&self) -> bool {
+        let inputs_left = self
+            .replay
+            .inputs
+            .get(self.next_input)
+            .is_some_and(|(frame, _)| *frame <= self.state.frame);
+        self.state.game_over || (self.state.frame >= self.replay.frames && !inputs_left)
+    }
+    /// Applies the inputs recorded for the current frame, then ticks gravity.
+    pub fn step(&mut self) -> Vec<Event> {
+        let mut events = Vec::new();
+        while let Some((frame, change)) = self.replay.inputs.get(self.next_input) {
+            if *frame > self.state.frame {
+                break;
+            }
+            events.extend(self.state.apply(*change));
+            self.next_input += 1;
+        }
+        if self.state.frame < self.replay.frames {
+            events.extend(self.state.tick());
+        }
+        events
+    }
+    /// Jumps to `frame`. Going backwards replays the game from the start.
+    pub fn seek(&mut self, frame: u64) {
+        if frame < self.state.frame {
+            self.state = self.replay.new_game();
+            self.next_input = 0;
+        }
+        while self.state.frame < frame && !self.is_finished() {
+            self.step();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::COLS;
+
+    #[test]
+    fn custom_piece_sets_are_stored_in_the_replay() {
+        let pieces = format!(
+            "[kicks]\nnone = [{}]\n[[piece]]\nname = \"P\"\ncolor = [9, 9, 9]\n\
+             kicks = \"none\"\nspawn = [1, 0]\nshapes = [{bar}, {bar}, {bar}, {bar}]\n",
+            ["[[0, 0]]"; 8].join(", "),
+            bar = "[\"###\", \"#..\", \"...\"]",
+        );
+        let config = Config {
+            pieces: Arc::new(pieces.parse().unwrap()),
+            ..Default::default()
+        };
+        let mut state = AppState::from_seed(config, 5);
+        let mut replay = Replay::for_game(&state);
+        for frame in 0..3 {
+            state.apply(StateChange::HardDrop);
+            replay.record(frame, StateChange::HardDrop);
+        }
+
+        let text = replay.to_string();
+        assert!(text.contains("\npieces "));
+        let loaded: Replay = text.parse().unwrap();
+        assert_eq!(loaded, replay);
+        let mut playback = Playback::new(loaded);
+        while !playback.is_finished() {
+            playback.step();
+        }
+        assert_eq!(playback.state.board, state.board);
+    }
+
+    #[test]
+    fn replays_without_a_randomizer_use_the_modes() {
+        let replay: Replay = "tetris-replay 6\nseed 1\nmode cheese-5\nend 0\n"
+            .parse()
+            .unwrap();
+        assert_eq!(replay.randomizer, RandomizerKind::Tgm);
+        let replay: Replay = "tetris-replay 6\nseed 1\nmode cheese-5\nrandomizer nes\nend 0\n"
+            .parse()
+            .unwrap();
+        assert_eq!(replay.randomizer, RandomizerKind::Nes);
+        // Before randomizers could be picked every mode used the 7-bag
+        let replay: Replay = "tetris-replay 4\nseed 1\nmode cheese-5\nend 0\n"
+            .parse()
+            .unwrap();
+        assert_eq!(replay.randomizer, RandomizerKind::SevenBag);
+    }
+
+    #[test]
+    fn older_replays_use_the_standard_pieces() {
+        let replay: Replay = "tetris-replay 5\nseed 1\nend 0\n".parse().unwrap();
+        assert_eq!(replay.pieces, PieceSet::tetrominoes());
+        assert_eq!(replay.width, COLS);
+        assert!(!replay.to_string().contains("pieces"));
+    }
+
+    #[test]
+    fn cheese_height_is_stored_in_the_replay() {
+        let config = Config {
+            mode: Mode::Cheese { lines: 18 },
+            cheese_height: 4,
+            ..Default::default()
+        };
+        let state = AppState::from_seed(config, 3);
+        let loaded: Replay = Replay::for_game(&state).to_string().parse().unwrap();
+        assert_eq!(loaded.cheese_height, 4);
+        assert_eq!(loaded.new_game().board, state.board);
+        // Older replays dug through the default height
+        let replay: Replay = "tetris-replay 6\nseed 1\nmode cheese-5\nend 0\n"
+            .parse()
+            .unwrap();
+        assert_eq!(replay.cheese_height, Config::default().cheese_height);
+    }
+
+    #[test]
+    fn gravity_is_stored_in_the_replay() {
+        let config = Config {
+            gravity: vec![0.5, 1.25, 20.0],
+            ..Default::default()
+        };
+        let replay = Replay::for_game(&AppState::from_seed(config, 1));
+        assert!(replay.to_string().contains("\ngravity 0.5 1.25 20\n"));
+        let loaded: Replay = replay.to_string().parse().unwrap();
+        assert_eq!(loaded.new_game().config.gravity, [0.5, 1.25, 20.0]);
+
+        let replay: Replay = "tetris-replay 8\nseed 1\nmode sprint-40\nend 0\n"
+            .parse()
+            .unwrap();
+        assert_eq!(replay.gravity, Mode::Sprint { lines: 40 }.gravity());
+        // Older replays fell with the guideline curve whatever the mode
+        let replay: Replay = "tetris-replay 7\nseed 1\nmode sprint-40\nend 0\n"
+            .parse()
+            .unwrap();
+        assert_eq!(replay.gravity, guideline_gravity());
+    }
+}
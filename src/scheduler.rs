@@ -0,0 +1,59 @@
+use std::time::Instant;
+use tetris::FRAME;
+
+/// Frames to catch up on at most after a stall, so a long pause doesn't fast-forward the game.
+const MAX_CATCH_UP: u32 = 5;
+
+/// Runs the engine at a fixed [`FRAME`] rate no matter how often the event loop wakes up.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Scheduler {
+    next_frame: Instant,
+}
+impl Scheduler {
+    pub(crate) fn new() -> Self {
+        Scheduler {
+            next_frame: Instant::now() + FRAME,
+        }
+    }
+    /// How many frames have come due by `now`. Moves the schedule past them.
+    pub(crate) fn due_frames(&mut self, now: Instant) -> u32 {
+        let mut frames = 0;
+        while self.next_frame <= now {
+            frames += 1;
+            self.next_frame += FRAME;
+            if frames == MAX_CATCH_UP {
+                self.next_frame = now + FRAME;
+                break;
+            }
+        }
+        frames
+    }
+    pub(crate) fn next_frame(&self) -> Instant {
+        self.next_frame
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_come_due_one_per_frame() {
+        let mut scheduler = Scheduler::new();
+        let first = scheduler.next_frame();
+        assert_eq!(scheduler.due_frames(first - FRAME / 2), 0);
+        assert_eq!(scheduler.due_frames(first), 1);
+        assert_eq!(scheduler.due_frames(first + FRAME * 2 + FRAME / 2), 2);
+        assert_eq!(scheduler.next_frame(), first + FRAME * 3);
+    }
+
+    #[test]
+    fn a_stall_only_catches_up_a_few_frames() {
+        let mut scheduler = Scheduler::new();
+        let now = scheduler.next_frame() + FRAME * 100;
+        assert_eq!(scheduler.due_frames(now), MAX_CATCH_UP);
+        // The rest are dropped rather than run later
+        assert_eq!(scheduler.next_frame(), now + FRAME);
+        assert_eq!(scheduler.due_frames(now), 0);
+    }
+}
@@ -0,0 +1,156 @@
+/// Extra garbage for each step of a combo, indexed by [`Clear::combo`]. Longer combos
+/// use the last entry.
+const COMBO_ATTACK: [u32; 11] = [0, 1, 1, 2, 2, 3, 3, 4, 4, 4, 5];
+/// Extra garbage for leaving the board empty.
+const PERFECT_CLEAR_ATTACK: u32 = 10;
+
+/// Whether a lock counted as a T-spin.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TSpin {
+    #[default]
+    None,
+    Mini,
+    Full,
+}
+
+/// What a single lock achieved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Clear {
+    pub lines: u8,
+    pub t_spin: TSpin,
+    /// This clear continued a back-to-back chain of difficult clears.
+    pub back_to_back: bool,
+    /// Line-clearing locks in a row before this one, if this one cleared lines.
+    pub combo: u32,
+    /// The clear left the board empty.
+    pub perfect_clear: bool,
+}
+impl Clear {
+    /// Tetrises and line-clearing T-spins. These keep a back-to-back chain going. Pieces
+    /// from bigger sets can clear more than 4 lines, which counts as a tetris too.
+    pub fn is_difficult(self) -> bool {
+        self.lines >= 4 || (self.lines > 0 && self.t_spin != TSpin::None)
+    }
+    /// Points for this clear at `level`, with the back-to-back, combo and perfect clear
+    /// bonuses.
+    pub fn score(self, level: u32) -> u64 {
+        let level = level as u64;
+        let mut points = self.points() * level;
+        if self.back_to_back {
+            points = points * 3 / 2;
+        }
+        points + 50 * self.combo as u64 * level + self.perfect_clear_points() * level
+    }
+    /// Garbage lines this clear sends to an opponent, before cancelling any incoming.
+    pub fn attack(self) -> u32 {
+        if self.lines == 0 {
+            return 0;
+        }
+        let lines = match (self.t_spin, self.lines) {
+            (TSpin::None, lines @ 0..=3) => [0, 0, 1, 2][lines as usize],
+            (TSpin::None, lines) => lines as u32,
+            (TSpin::Mini, lines) => lines as u32 - 1,
+            (TSpin::Full, lines) => 2 * lines as u32,
+        };
+        let combo = COMBO_ATTACK[(self.combo as usize).min(COMBO_ATTACK.len() - 1)];
+        let perfect_clear = if self.perfect_clear {
+            PERFECT_CLEAR_ATTACK
+        } else {
+            0
+        };
+        lines + u32::from(self.back_to_back) + combo + perfect_clear
+    }
+    /// Guideline points for this clear at level 1. The guideline stops at tetrises and
+    /// T-spin triples, so bigger clears from other piece sets carry on its steps: 400 a
+    /// line past a tetris, and 200 a line past a mini T-spin double.
+    pub fn points(self) -> u64 {
+        let lines = self.lines as u64;
+        match (self.t_spin, self.lines) {
+            (TSpin::None, 0) => 0,
+            (TSpin::None, 1) => 100,
+            (TSpin::None, 2) => 300,
+            (TSpin::None, 3) => 500,
+            (TSpin::None, _) => 800 + 400 * (lines - 4),
+            (TSpin::Mini, 0) => 100,
+            (TSpin::Mini, _) => 200 * lines,
+            (TSpin::Full, _) => 400 * (lines + 1),
+        }
+    }
+    /// Guideline perfect clear bonus at level 1, on top of [`Clear::points`]. A perfect
+    /// clear tetris that is also back-to-back gets more.
+    pub fn perfect_clear_points(self) -> u64 {
+        if !self.perfect_clear {
+            return 0;
+        }
+        match (self.lines, self.back_to_back) {
+            (4.., true) => 3200,
+            (4.., false) => 2000,
+            (3, _) => 1800,
+            (2, _) => 1200,
+            (1, _) => 800,
+            _ => 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clear(lines: u8, t_spin: TSpin) -> Clear {
+        Clear {
+            lines,
+            t_spin,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn guideline_points() {
+        let points = |lines, t_spin| clear(lines, t_spin).points();
+        assert_eq!(points(1, TSpin::None), 100);
+        assert_eq!(points(4, TSpin::None), 800);
+        assert_eq!(points(0, TSpin::Mini), 100);
+        assert_eq!(points(1, TSpin::Mini), 200);
+        assert_eq!(points(2, TSpin::Mini), 400);
+        assert_eq!(points(0, TSpin::Full), 400);
+        assert_eq!(points(3, TSpin::Full), 1600);
+    }
+
+    #[test]
+    fn clears_past_the_guideline_tables_score() {
+        assert_eq!(clear(5, TSpin::None).points(), 1200);
+        assert_eq!(clear(3, TSpin::Mini).points(), 600);
+        assert_eq!(clear(4, TSpin::Full).points(), 2000);
+        assert_eq!(clear(5, TSpin::None).attack(), 5);
+        assert_eq!(clear(3, TSpin::Mini).attack(), 2);
+        assert!(clear(5, TSpin::None).is_difficult());
+    }
+
+    #[test]
+    fn back_to_back_and_combo_bonuses() {
+        let tetris = Clear {
+            back_to_back: true,
+            combo: 2,
+            ..clear(4, TSpin::None)
+        };
+        assert_eq!(tetris.score(2), 800 * 2 * 3 / 2 + 50 * 2 * 2);
+        assert_eq!(tetris.attack(), 4 + 1 + 1);
+        assert!(clear(1, TSpin::Mini).is_difficult());
+        assert!(!clear(0, TSpin::Full).is_difficult());
+    }
+
+    #[test]
+    fn perfect_clear_bonus() {
+        let perfect = |lines, back_to_back| Clear {
+            perfect_clear: true,
+            back_to_back,
+            ..clear(lines, TSpin::None)
+        };
+        assert_eq!(perfect(1, false).perfect_clear_points(), 800);
+        assert_eq!(perfect(4, false).perfect_clear_points(), 2000);
+        assert_eq!(perfect(4, true).perfect_clear_points(), 3200);
+        assert_eq!(perfect(2, false).score(1), 300 + 1200);
+        assert_eq!(perfect(1, false).attack(), PERFECT_CLEAR_ATTACK);
+    }
+}
@@ -0,0 +1,817 @@
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+use tetris::{
+    AppState, Bot, Config, Connection, Controls, Event, Handling, Message, NetError, Playback,
+    Replay, Snapshot, StateChange, TbpBot, TbpError, FRAME,
+};
+use winit::keyboard::{Key, NamedKey};
+
+use crate::{
+    bindings::{key_names, Action, Bindings},
+    highscores::{HighScore, HighScores},
+    rendering::Hud,
+};
+
+/// Frames the bot waits before playing each piece, so it can be followed.
+const BOT_DELAY: u32 = 15;
+/// How far the seek keys jump during playback.
+const SEEK_FRAMES: u64 = 5 * 60;
+const MIN_SPEED: f32 = 0.125;
+const MAX_SPEED: f32 = 16.0;
+
+/// What the window is showing: a game being played by a person or the bot, a recording
+/// being watched, or a match against other players.
+// There is only ever one session, so boxing the bigger variant buys nothing.
+#[allow(clippy::large_enum_variant)]
+pub(crate) enum Session {
+    Live(Live),
+    Watch(Viewer),
+    Versus(Versus),
+    Online(Online),
+    Autoplay(Autoplay),
+}
+impl Session {
+    pub(crate) fn step(&mut self) {
+        match self {
+            Session::Live(live) => live.step(),
+            Session::Watch(viewer) => viewer.step(),
+            Session::Versus(versus) => versus.step(),
+            Session::Online(online) => online.step(),
+            Session::Autoplay(autoplay) => autoplay.step(),
+        }
+    }
+    /// Every board on screen, left to right, with what to draw around it. `bindings`
+    /// name the keys in the help text.
+    pub(crate) fn boards(&self, bindings: &[Bindings]) -> Vec<(&AppState, Hud)> {
+        let pause_keys = key_names(bindings, Action::Pause);
+        let restart_keys = key_names(bindings, Action::Restart);
+        match self {
+            Session::Live(live) => vec![(
+                &live.state,
+                Hud {
+                    paused: live.paused,
+                    high_scores: live.scores.table(&live.state.config).to_vec(),
+                    new_high_score: live.new_high_score,
+                    time: Some(live.clock.elapsed()),
+                    pause_keys,
+                    restart_keys,
+                    ..Default::default()
+                },
+            )],
+            Session::Watch(viewer) => vec![(
+                &viewer.playback.state,
+                Hud {
+                    status: Some(viewer.status()),
+                    ..Default::default()
+                },
+            )],
+            Session::Versus(versus) => versus
+                .players
+                .iter()
+                .map(|player| {
+                    let verdict =
+                        (versus.is_over() && !player.state.game_over).then(|| "Winner".to_string());
+                    let hud = Hud {
+                        paused: versus.paused,
+                        verdict,
+                        pause_keys: pause_keys.clone(),
+                        restart_keys: restart_keys.clone(),
+                        ..Default::default()
+                    };
+                    (&player.state, hud)
+                })
+                .collect(),
+            Session::Online(online) => {
+                let own = Hud {
+                    status: online.status.clone(),
+                    verdict: (online.opponent.game_over && !online.player.state.game_over)
+                        .then(|| "Winner".to_string()),
+                    restart_keys: restart_keys.clone(),
+                    ..Default::default()
+                };
+                // Restarting starts both boards over
+                let opponent = Hud {
+                    verdict: (online.player.state.game_over && !online.opponent.game_over)
+                        .then(|| "Winner".to_string()),
+                    restart_keys,
+                    ..Default::default()
+                };
+                vec![(&online.player.state, own), (&online.opponent, opponent)]
+            }
+            Session::Autoplay(autoplay) => vec![(
+                &autoplay.state,
+                Hud {
+                    paused: autoplay.paused,
+                    status: Some(autoplay.status()),
+                    pause_keys,
+                    restart_keys,
+                    ..Default::default()
+                },
+            )],
+        }
+    }
+}
+
+/// A game driven by the keyboard, recorded as it is played.
+pub(crate) struct Live {
+    pub(crate) state: AppState,
+    pub(crate) controls: Controls,
+    /// Gravity and input are frozen and the field is hidden.
+    pub(crate) paused: bool,
+    clock: Stopwatch,
+    /// Seed every game starts from. Each game picks a new random seed if this is missing.
+    seed: Option<u64>,
+    recording: Replay,
+    record_path: Option<PathBuf>,
+    /// Games played so far, counting the current one. Each is recorded to its own file.
+    games: u32,
+    scores: HighScores,
+    player: String,
+    /// Rank the last finished game got in the high score table.
+    new_high_score: Option<usize>,
+}
+impl Live {
+    pub(crate) fn new(
+        config: Config,
+        seed: Option<u64>,
+        handling: Handling,
+        record_path: Option<PathBuf>,
+        scores: HighScores,
+        player: String,
+    ) -> Self {
+        let state = new_player_game(&config, seed, 0);
+        Live {
+            recording: Replay::for_game(&state),
+            state,
+            controls: Controls::new(handling),
+            paused: false,
+            clock: Stopwatch::default(),
+            seed,
+            record_path,
+            games: 1,
+            scores,
+            player,
+            new_high_score: None,
+        }
+    }
+    pub(crate) fn toggle_pause(&mut self) {
+        if self.state.game_over {
+            return;
+        }
+        self.paused = !self.paused;
+        if self.paused {
+            self.clock.stop();
+        } else {
+            self.clock.resume();
+        }
+        // Keys released while paused never reach the controls
+        self.controls = Controls::new(self.controls.handling);
+    }
+    /// Throws the current game away and starts a new one with the same settings and seed.
+    pub(crate) fn restart(&mut self) {
+        if !self.state.game_over {
+            self.save_recording();
+        }
+        self.state = new_player_game(&self.state.config, self.seed, 0);
+        self.recording = Replay::for_game(&self.state);
+        self.games += 1;
+        self.clock = Stopwatch::default();
+        self.controls = Controls::new(self.controls.handling);
+        self.paused = false;
+        self.new_high_score = None;
+    }
+    pub(crate) fn apply(&mut self, change: StateChange) {
+        if self.state.game_over || self.paused {
+            return;
+        }
+        self.clock.start();
+        let (frame, location, started) =
+            (self.state.frame, self.state.location, self.state.started);
+        let events = self.state.apply(change);
+        // Held keys repeat every frame even against a wall. Inputs that neither moved the
+        // piece nor caused anything are left out, except the first, which starts the
+        // game's clock.
+        if !events.is_empty() || self.state.location != location || started.is_none() {
+            self.recording.record(frame, change);
+        }
+        self.handle_events(&events);
+    }
+    fn step(&mut self) {
+        if self.state.game_over || self.paused {
+            return;
+        }
+        let (width, height) = (self.state.width(), self.state.board.num_rows());
+        for change in self.controls.tick(width, height) {
+            self.apply(change);
+        }
+        let events = self.state.tick();
+        self.handle_events(&events);
+    }
+    fn handle_events(&mut self, events: &[Event]) {
+        if events.contains(&Event::GameOver) {
+            self.clock.stop();
+            self.save_recording();
+            self.record_high_score();
+        }
+    }
+    fn record_high_score(&mut self) {
+        let mode = self.state.config.mode;
+        // A run against the clock that topped out has no time to rank.
+        if mode.ranked_by_time() && !self.state.completed {
+            return;
+        }
+        let entry = HighScore::new(&self.player, &self.state, self.clock.elapsed());
+        self.new_high_score = self.scores.insert(&self.state.config, entry);
+        if self.new_high_score.is_some() {
+            if let Err(err) = self.scores.save() {
+                eprintln!("Could not save high scores: {err}");
+            }
+        }
+    }
+    /// Writes the recording so far to the `--record` path, if there is one. Games after
+    /// the first are numbered, so restarting never overwrites an earlier game.
+    pub(crate) fn save_recording(&mut self) {
+        let Some(path) = &self.record_path else {
+            return;
+        };
+        let path = numbered(path, self.games);
+        self.recording.frames = self.state.frame;
+        if let Err(err) = self.recording.save(&path) {
+            eprintln!("Could not save replay to {}: {err}", path.display());
+        }
+    }
+}
+
+/// Whichever bot is playing: the built-in one or an external one speaking TBP.
+pub(crate) enum Driver {
+    Builtin(Bot),
+    External(TbpBot),
+}
+impl Driver {
+    pub(crate) fn name(&self) -> String {
+        match self {
+            Driver::Builtin(_) => "Bot".to_string(),
+            Driver::External(bot) => format!("{} {}", bot.info.name, bot.info.version),
+        }
+    }
+    /// Tells the bot a new game has started.
+    pub(crate) fn start(&mut self, state: &AppState) -> Result<(), TbpError> {
+        match self {
+            Driver::Builtin(_) => Ok(()),
+            Driver::External(bot) => bot.start(state),
+        }
+    }
+    /// Plays a piece, waiting for the bot to decide.
+    pub(crate) fn play(&mut self, state: &mut AppState) -> Result<Vec<Event>, TbpError> {
+        match self {
+            Driver::Builtin(bot) => Ok(bot.play(state)),
+            Driver::External(bot) => bot.play(state),
+        }
+    }
+    /// Plays a piece if the bot has decided on one, without waiting.
+    fn try_play(&mut self, state: &mut AppState) -> Result<Option<Vec<Event>>, TbpError> {
+        match self {
+            Driver::Builtin(bot) => Ok(Some(bot.play(state))),
+            Driver::External(bot) => bot.try_play(state),
+        }
+    }
+}
+
+/// A bot playing a game on its own.
+pub(crate) struct Autoplay {
+    pub(crate) state: AppState,
+    pub(crate) paused: bool,
+    /// Seed every game starts from. Each game picks a new random seed if this is missing.
+    seed: Option<u64>,
+    driver: Driver,
+    /// Frames since the bot last played.
+    waited: u32,
+    /// Why the bot stopped playing, if it failed.
+    error: Option<String>,
+}
+impl Autoplay {
+    pub(crate) fn new(config: Config, seed: Option<u64>, driver: Driver) -> Self {
+        Autoplay {
+            state: new_player_game(&config, seed, 0),
+            paused: false,
+            seed,
+            driver,
+            waited: 0,
+            error: None,
+        }
+    }
+    pub(crate) fn toggle_pause(&mut self) {
+        if !self.state.game_over {
+            self.paused = !self.paused;
+        }
+    }
+    /// Starts a new game with the same settings and seed.
+    pub(crate) fn restart(&mut self) {
+        self.state = new_player_game(&self.state.config, self.seed, 0);
+        self.paused = false;
+        self.waited = 0;
+        if self.error.is_none() {
+            if let Err(err) = self.driver.start(&self.state) {
+                self.fail(err);
+            }
+        }
+    }
+    fn step(&mut self) {
+        if self.state.game_over || self.paused || self.error.is_some() {
+            return;
+        }
+        // The clock stops while the bot thinks, so gravity can't move the piece it is placing
+        if self.waited < BOT_DELAY {
+            self.state.tick();
+            self.waited += 1;
+        }
+        if self.waited >= BOT_DELAY {
+            match self.driver.try_play(&mut self.state) {
+                Ok(Some(_)) => self.waited = 0,
+                Ok(None) => {}
+                Err(err) => self.fail(err),
+            }
+        }
+    }
+    fn fail(&mut self, err: TbpError) {
+        self.error = Some(format!("{} failed: {err}", self.driver.name()));
+    }
+    fn status(&self) -> String {
+        match &self.error {
+            Some(error) => error.clone(),
+            None => format!("{} playing", self.driver.name()),
+        }
+    }
+}
+
+/// One side of a local match.
+pub(crate) struct Player {
+    pub(crate) state: AppState,
+    pub(crate) controls: Controls,
+}
+
+/// A local match with a board per player. Line clears send garbage to the next player
+/// along who is still in, and the match ends when only one is left standing.
+pub(crate) struct Versus {
+    pub(crate) players: Vec<Player>,
+    pub(crate) paused: bool,
+    /// Seed for the first player's randomizer, the rest counting up from it. Each match
+    /// picks new random seeds if this is missing.
+    seed: Option<u64>,
+    config: Config,
+}
+impl Versus {
+    pub(crate) fn new(handling: Vec<Handling>, seed: Option<u64>, config: Config) -> Self {
+        let players = handling
+            .into_iter()
+            .enumerate()
+            .map(|(seat, handling)| Player {
+                state: new_player_game(&config, seed, seat),
+                controls: Controls::new(handling),
+            })
+            .collect();
+        Versus {
+            players,
+            paused: false,
+            seed,
+            config,
+        }
+    }
+    /// Whether at most one player is still in. That player, if any, has won.
+    pub(crate) fn is_over(&self) -> bool {
+        self.players
+            .iter()
+            .filter(|player| !player.state.game_over)
+            .count()
+            <= 1
+    }
+    pub(crate) fn toggle_pause(&mut self) {
+        if self.is_over() {
+            return;
+        }
+        self.paused = !self.paused;
+        for player in &mut self.players {
+            player.controls = Controls::new(player.controls.handling);
+        }
+    }
+    /// Starts a new match with every board empty.
+    pub(crate) fn restart(&mut self) {
+        for (seat, player) in self.players.iter_mut().enumerate() {
+            player.state = new_player_game(&self.config, self.seed, seat);
+            player.controls = Controls::new(player.controls.handling);
+        }
+        self.paused = false;
+    }
+    pub(crate) fn apply(&mut self, seat: usize, change: StateChange) {
+        if self.is_over() || self.paused {
+            return;
+        }
+        let events = self.players[seat].state.apply(change);
+        self.send_attacks(seat, &events);
+    }
+    fn step(&mut self) {
+        for seat in 0..self.players.len() {
+            if self.is_over() || self.paused {
+                return;
+            }
+            let state = &self.players[seat].state;
+            let (width, height) = (state.width(), state.board.num_rows());
+            for change in self.players[seat].controls.tick(width, height) {
+                self.apply(seat, change);
+            }
+            let events = self.players[seat].state.tick();
+            self.send_attacks(seat, &events);
+        }
+    }
+    fn send_attacks(&mut self, seat: usize, events: &[Event]) {
+        let count = self.players.len();
+        let Some(target) = (1..count)
+            .map(|offset| (seat + offset) % count)
+            .find(|target| !self.players[*target].state.game_over)
+        else {
+            return;
+        };
+        for event in events {
+            if let Event::Attack(lines) = event {
+                self.players[target].state.receive_garbage(*lines);
+            }
+        }
+    }
+}
+
+/// A match against another copy of the game over the network. Only our own game runs
+/// here. The opponent's board is drawn from the snapshots they send.
+pub(crate) struct Online {
+    pub(crate) player: Player,
+    opponent: AppState,
+    /// `None` once the connection has failed.
+    connection: Option<Connection>,
+    /// The last snapshot sent, so unchanged frames aren't sent again.
+    sent: Option<Snapshot>,
+    status: Option<String>,
+}
+impl Online {
+    pub(crate) fn new(connection: Connection, handling: Handling, config: Config) -> Self {
+        Online {
+            player: Player {
+                state: AppState::with_config(config.clone()),
+                controls: Controls::new(handling),
+            },
+            // Drawn with our pieces, in case both sides play the same custom set
+            opponent: AppState::with_config(config),
+            connection: Some(connection),
+            sent: None,
+            status: None,
+        }
+    }
+    pub(crate) fn is_over(&self) -> bool {
+        self.player.state.game_over || self.opponent.game_over || self.connection.is_none()
+    }
+    /// Starts a new match here and asks the other side to do the same.
+    pub(crate) fn restart(&mut self) {
+        if self.connection.is_none() {
+            return;
+        }
+        self.reset();
+        self.send(&Message::Restart);
+    }
+    fn reset(&mut self) {
+        self.player.state = AppState::with_config(self.player.state.config.clone());
+        self.player.controls = Controls::new(self.player.controls.handling);
+        self.opponent = AppState::with_config(self.player.state.config.clone());
+        self.sent = None;
+    }
+    pub(crate) fn apply(&mut self, change: StateChange) {
+        if self.is_over() {
+            return;
+        }
+        let events = self.player.state.apply(change);
+        self.handle_events(&events);
+    }
+    fn step(&mut self) {
+        self.receive();
+        if !self.is_over() {
+            let (width, height) = (
+                self.player.state.width(),
+                self.player.state.board.num_rows(),
+            );
+            for change in self.player.controls.tick(width, height) {
+                self.apply(change);
+            }
+            let events = self.player.state.tick();
+            self.handle_events(&events);
+        }
+        let snapshot = Snapshot::of(&self.player.state);
+        if self.sent.as_ref() != Some(&snapshot) {
+            self.send(&Message::State(snapshot.clone()));
+            self.sent = Some(snapshot);
+        }
+    }
+    fn receive(&mut self) {
+        let Some(connection) = &mut self.connection else {
+            return;
+        };
+        let messages = match connection.poll() {
+            Ok(messages) => messages,
+            Err(err) => return self.disconnect(err),
+        };
+        for message in messages {
+            match message {
+                Message::State(snapshot) => snapshot.apply_to(&mut self.opponent),
+                Message::Garbage(lines) => self.player.state.receive_garbage(lines),
+                Message::GameOver => self.opponent.game_over = true,
+                Message::Restart => self.reset(),
+            }
+        }
+    }
+    fn handle_events(&mut self, events: &[Event]) {
+        for event in events {
+            match event {
+                Event::Attack(lines) => self.send(&Message::Garbage(*lines)),
+                Event::GameOver => self.send(&Message::GameOver),
+                _ => {}
+            }
+        }
+    }
+    fn send(&mut self, message: &Message) {
+        if let Some(connection) = &mut self.connection {
+            if let Err(err) = connection.send(message) {
+                self.disconnect(err);
+            }
+        }
+    }
+    fn disconnect(&mut self, err: NetError) {
+        self.connection = None;
+        self.status = Some(format!("Connection lost: {err}"));
+    }
+}
+
+/// Real time since the first input, to the millisecond, leaving out pauses. The game's
+/// own clock counts whole frames, so it can't time a run any finer than about 17 ms.
+#[derive(Debug, Clone, Copy, Default)]
+struct Stopwatch {
+    /// Time up to the last stop.
+    elapsed: Duration,
+    /// When the watch last started running, while it runs.
+    running_since: Option<Instant>,
+    started: bool,
+}
+impl Stopwatch {
+    /// Starts the watch the first time it is called, and does nothing after that.
+    fn start(&mut self) {
+        if !self.started {
+            self.started = true;
+            self.running_since = Some(Instant::now());
+        }
+    }
+    fn stop(&mut self) {
+        if let Some(since) = self.running_since.take() {
+            self.elapsed += since.elapsed();
+        }
+    }
+    /// Carries on after a [`Stopwatch::stop`], if the watch was ever started.
+    fn resume(&mut self) {
+        if self.started && self.running_since.is_none() {
+            self.running_since = Some(Instant::now());
+        }
+    }
+    fn elapsed(&self) -> Duration {
+        self.elapsed
+            + self
+                .running_since
+                .map_or(Duration::ZERO, |since| since.elapsed())
+    }
+}
+
+/// `path` for the first game, then `path` with `-2`, `-3` and so on before the extension.
+fn numbered(path: &Path, game: u32) -> PathBuf {
+    if game <= 1 {
+        return path.to_path_buf();
+    }
+    let mut name = path.file_stem().unwrap_or_default().to_os_string();
+    name.push(format!("-{game}"));
+    if let Some(extension) = path.extension() {
+        name.push(".");
+        name.push(extension);
+    }
+    path.with_file_name(name)
+}
+
+fn new_player_game(config: &Config, seed: Option<u64>, seat: usize) -> AppState {
+    match seed {
+        Some(seed) => AppState::from_seed(config.clone(), seed.wrapping_add(seat as u64)),
+        None => AppState::with_config(config.clone()),
+    }
+}
+
+/// Plays a recording back with pause, seek and speed controls.
+pub(crate) struct Viewer {
+    pub(crate) playback: Playback,
+    paused: bool,
+    speed: f32,
+    /// Fraction of a frame owed from running at a non-integer speed.
+    progress: f32,
+}
+impl Viewer {
+    pub(crate) fn new(replay: Replay) -> Self {
+        Viewer {
+            playback: Playback::new(replay),
+            paused: false,
+            speed: 1.0,
+            progress: 0.0,
+        }
+    }
+    fn step(&mut self) {
+        if self.paused {
+            return;
+        }
+        self.progress += self.speed;
+        while self.progress >= 1.0 && !self.playback.is_finished() {
+            self.progress -= 1.0;
+            self.playback.step();
+        }
+    }
+    /// Space pauses, left and right seek, up and down change the speed, Home restarts.
+    pub(crate) fn handle_key(&mut self, key: &Key) {
+        let frame = self.playback.state.frame;
+        match key {
+            Key::Named(NamedKey::Space) => self.paused = !self.paused,
+            Key::Named(NamedKey::ArrowLeft) => {
+                self.playback.seek(frame.saturating_sub(SEEK_FRAMES));
+            }
+            Key::Named(NamedKey::ArrowRight) => self.playback.seek(frame + SEEK_FRAMES),
+            Key::Named(NamedKey::ArrowUp) => self.speed = (self.speed * 2.0).min(MAX_SPEED),
+            Key::Named(NamedKey::ArrowDown) => self.speed = (self.speed / 2.0).max(MIN_SPEED),
+            Key::Named(NamedKey::Home) => self.playback.seek(0),
+            _ => {}
+        }
+    }
+    fn status(&self) -> String {
+        let elapsed = (FRAME * self.playback.state.frame as u32).as_secs();
+        let total = self.playback.replay.duration().as_secs();
+        format!(
+            "Replay {}:{:02} / {}:{:02}  x{}{}",
+            elapsed / 60,
+            elapsed % 60,
+            total / 60,
+            total % 60,
+            self.speed,
+            if self.paused { "  Paused" } else { "" },
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread::sleep;
+    use tetris::MovementType;
+
+    #[test]
+    fn the_stopwatch_leaves_out_time_before_the_start_and_while_stopped() {
+        let tick = Duration::from_millis(20);
+        let mut clock = Stopwatch::default();
+        clock.resume();
+        sleep(tick);
+        assert_eq!(clock.elapsed(), Duration::ZERO);
+
+        clock.start();
+        sleep(tick);
+        clock.stop();
+        let stopped = clock.elapsed();
+        assert!(stopped >= tick, "{stopped:?}");
+        sleep(tick);
+        assert_eq!(clock.elapsed(), stopped);
+
+        // Starting again doesn't reset it, resuming carries on from where it stopped
+        clock.start();
+        assert_eq!(clock.elapsed(), stopped);
+        clock.resume();
+        sleep(tick);
+        clock.stop();
+        assert!(clock.elapsed() >= stopped + tick);
+    }
+
+    #[test]
+    fn recorded_games_play_back_the_same() {
+        let path = std::env::temp_dir().join(format!("tetris-live-{}.replay", std::process::id()));
+        // Instant auto repeat and soft drop send a burst of inputs every frame a key is held
+        let handling = Handling {
+            arr: Duration::ZERO,
+            soft_drop: Duration::ZERO,
+            ..Default::default()
+        };
+        let mut live = Live::new(
+            Config::default(),
+            Some(9),
+            handling,
+            Some(path.clone()),
+            HighScores::default(),
+            String::new(),
+        );
+        let press = |live: &mut Live, change| {
+            for change in live.controls.press(change) {
+                live.apply(change);
+            }
+        };
+        for piece in 0..30 {
+            let direction = if piece % 2 == 0 {
+                MovementType::Left
+            } else {
+                MovementType::Right
+            };
+            press(&mut live, StateChange::Move(direction));
+            if piece % 3 == 0 {
+                press(&mut live, StateChange::Rotate(MovementType::Right));
+            }
+            for _ in 0..piece % 4 * 10 {
+                live.step();
+            }
+            live.controls.release(StateChange::Move(direction));
+            press(&mut live, StateChange::SoftDrop);
+            for _ in 0..3 {
+                live.step();
+            }
+            live.controls.release(StateChange::SoftDrop);
+            press(&mut live, StateChange::HardDrop);
+            live.step();
+        }
+        live.save_recording();
+
+        let replay = Replay::load(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        // Every input kept after the first moved the piece or caused something
+        let mut state = replay.new_game();
+        for (index, (frame, change)) in replay.inputs.iter().enumerate() {
+            while state.frame < *frame {
+                state.tick();
+            }
+            let location = state.location;
+            let events = state.apply(*change);
+            assert!(
+                index == 0 || !events.is_empty() || state.location != location,
+                "input {index} did nothing"
+            );
+        }
+        let mut playback = Playback::new(replay);
+        while !playback.is_finished() {
+            playback.step();
+        }
+        assert_eq!(playback.state.board, live.state.board);
+        assert_eq!(playback.state.score, live.state.score);
+        assert_eq!(playback.state.frame, live.state.frame);
+    }
+
+    fn attack(versus: &mut Versus, seat: usize, lines: u32) {
+        versus.send_attacks(seat, &[Event::Attack(lines)]);
+    }
+    fn pending(versus: &Versus) -> Vec<u32> {
+        let players = versus.players.iter();
+        players.map(|player| player.state.pending_garbage).collect()
+    }
+    fn top_out(versus: &mut Versus, seat: usize) {
+        versus.players[seat].state.game_over = true;
+    }
+    fn verdicts(versus: Versus) -> Vec<Option<String>> {
+        let session = Session::Versus(versus);
+        let boards = session.boards(&[]);
+        boards.into_iter().map(|(_, hud)| hud.verdict).collect()
+    }
+
+    #[test]
+    fn attacks_go_to_the_next_player_still_in() {
+        let mut versus = Versus::new(vec![Handling::default(); 3], Some(1), Config::default());
+        attack(&mut versus, 0, 2);
+        attack(&mut versus, 2, 1);
+        assert_eq!(pending(&versus), [1, 2, 0]);
+
+        top_out(&mut versus, 1);
+        attack(&mut versus, 0, 3);
+        assert_eq!(pending(&versus), [1, 2, 3]);
+        // Nobody is left to attack after the last one tops out
+        top_out(&mut versus, 0);
+        attack(&mut versus, 2, 4);
+        assert_eq!(pending(&versus), [1, 2, 3]);
+    }
+
+    #[test]
+    fn the_match_ends_with_one_player_left_standing() {
+        let mut versus = Versus::new(vec![Handling::default(); 3], Some(1), Config::default());
+        top_out(&mut versus, 0);
+        assert!(!versus.is_over());
+        assert_eq!(verdicts(versus), [None, None, None]);
+
+        let mut versus = Versus::new(vec![Handling::default(); 3], Some(1), Config::default());
+        top_out(&mut versus, 0);
+        top_out(&mut versus, 2);
+        assert!(versus.is_over());
+        assert_eq!(verdicts(versus), [None, Some("Winner".to_string()), None]);
+
+        let mut versus = Versus::new(vec![Handling::default(); 2], Some(1), Config::default());
+        top_out(&mut versus, 1);
+        assert!(versus.is_over());
+        assert_eq!(verdicts(versus), [Some("Winner".to_string()), None]);
+    }
+}
@@ -0,0 +1,690 @@
+//! Lets bots that speak the Tetris Bot Protocol play the game. TBP bots run as a separate
+//! process and exchange one JSON message per line over its stdin and stdout.
+//!
+//! The game tells the bot what the board looks like with `start`, asks for a move with
+//! `suggest`, confirms the move it played with `play` and reveals each new preview piece
+//! with `new_piece`. Suggested moves are looked up among the placements the built-in
+//! [`Bot`] can reach, and played with the inputs it found, so an external bot can't do
+//! anything a player couldn't.
+//!
+//! See <https://github.com/tetris-bot-protocol/tbp-spec> for the full protocol.
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeSet,
+    io::{self, BufRead, BufReader, Write},
+    process::{Child, ChildStdin, Command, Stdio},
+    sync::mpsc::{self, Receiver, TryRecvError},
+    thread,
+};
+use thiserror::Error;
+
+use crate::ai::{Bot, Placement};
+use crate::game::{AppState, Cell, Event, StateChange, COLS};
+use crate::pieces::PieceSet;
+use crate::tetromino::Piece;
+
+/// Rows in a TBP board, buffer included. Everything above our own board is sent empty.
+/// TBP boards are always [`COLS`] wide.
+const BOARD_ROWS: usize = 40;
+
+#[derive(Debug, Error)]
+pub enum TbpError {
+    #[error("could not talk to the bot: {0}")]
+    Io(#[from] io::Error),
+    #[error("the bot sent invalid JSON: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("the bot refused the game: {0}")]
+    Unsupported(String),
+    #[error("the bot sent {0} at the wrong time")]
+    Protocol(String),
+    #[error("the bot suggested no move the game allows")]
+    NoMove,
+    #[error("the bot exited")]
+    Exited,
+    #[error("TBP only supports boards {COLS} wide and at most {BOARD_ROWS} tall with the buffer")]
+    BoardSize,
+    #[error("TBP only supports the standard tetrominoes")]
+    Pieces,
+}
+
+/// Messages from the game to the bot.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum FrontendMessage {
+    Rules,
+    Start(Start),
+    Stop,
+    Suggest,
+    Play {
+        #[serde(rename = "move")]
+        mv: Move,
+    },
+    NewPiece {
+        piece: Piece,
+    },
+    Quit,
+}
+
+/// Messages from the bot to the game.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum BotMessage {
+    Info(Info),
+    Ready,
+    Error {
+        reason: String,
+    },
+    Suggestion {
+        moves: Vec<Move>,
+    },
+    /// Bots may send messages from newer versions of the protocol, which are ignored.
+    #[serde(other)]
+    Unknown,
+}
+impl BotMessage {
+    fn name(&self) -> &'static str {
+        match self {
+            BotMessage::Info(_) => "info",
+            BotMessage::Ready => "ready",
+            BotMessage::Error { .. } => "error",
+            BotMessage::Suggestion { .. } => "suggestion",
+            BotMessage::Unknown => "an unknown message",
+        }
+    }
+}
+
+/// What the bot says about itself when it starts.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Info {
+    pub name: String,
+    pub version: String,
+    pub author: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct Start {
+    hold: Option<Piece>,
+    /// The current piece followed by the previews.
+    queue: Vec<Piece>,
+    combo: u32,
+    back_to_back: bool,
+    /// Bottom row first, each cell a piece letter, `G` for garbage or empty.
+    board: Vec<Vec<Option<char>>>,
+}
+impl Start {
+    fn of(state: &AppState) -> Self {
+        let board = (0..BOARD_ROWS)
+            .map(|y| {
+                (0..COLS)
+                    .map(|col| {
+                        let cell = state
+                            .board
+                            .num_rows()
+                            .checked_sub(y + 1)
+                            .and_then(|row| state.board[(row, col)]);
+                        cell.map(|cell| match cell {
+                            Cell::Block(piece) => piece.to_char(),
+                            Cell::Garbage => 'G',
+                        })
+                    })
+                    .collect()
+            })
+            .collect();
+        Start {
+            hold: state.held,
+            queue: std::iter::once(state.piece.piece)
+                .chain(state.queue.iter().copied())
+                .collect(),
+            // TBP counts the clear that started the combo too
+            combo: state.combo.map_or(0, |combo| combo + 1),
+            back_to_back: state.back_to_back,
+            board,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Move {
+    location: Location,
+    spin: Spin,
+}
+
+/// Where a piece locks, given by the cell it rotates around. `y` counts up from the
+/// bottom row.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+struct Location {
+    #[serde(rename = "type")]
+    piece: Piece,
+    orientation: Orientation,
+    x: isize,
+    y: isize,
+}
+impl Location {
+    /// The cells the piece covers, as `(row, col)` on a board `height` rows tall.
+    fn cells(self, height: usize) -> BTreeSet<(isize, isize)> {
+        let north: [(isize, isize); 4] = match self.piece {
+            Piece::I => [(-1, 0), (0, 0), (1, 0), (2, 0)],
+            Piece::O => [(0, 0), (1, 0), (0, 1), (1, 1)],
+            Piece::T => [(-1, 0), (0, 0), (1, 0), (0, 1)],
+            Piece::L => [(-1, 0), (0, 0), (1, 0), (1, 1)],
+            Piece::J => [(-1, 0), (0, 0), (1, 0), (-1, 1)],
+            Piece::S => [(-1, 0), (0, 0), (0, 1), (1, 1)],
+            Piece::Z => [(-1, 1), (0, 1), (0, 0), (1, 0)],
+            // Not a TBP piece, so it matches no placement
+            _ => return BTreeSet::new(),
+        };
+        north
+            .into_iter()
+            .map(|(x, y)| match self.orientation {
+                Orientation::North => (x, y),
+                Orientation::East => (y, -x),
+                Orientation::South => (-x, -y),
+                Orientation::West => (-y, x),
+            })
+            .map(|(x, y)| (height as isize - 1 - (self.y + y), self.x + x))
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Orientation {
+    North,
+    East,
+    South,
+    West,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Spin {
+    None,
+    Mini,
+    Full,
+}
+
+/// The cells a placement covers, as board `(row, col)`.
+fn placement_cells(state: &AppState, placement: &Placement) -> BTreeSet<(isize, isize)> {
+    let (col, row) = placement.location;
+    let Some(blocks) = state.config.pieces.shape(placement.piece) else {
+        return BTreeSet::new();
+    };
+    blocks
+        .enumerate_row_major()
+        .filter(|(_, block)| **block)
+        .map(|((r, c), _)| (row + r as isize, col + c as isize))
+        .collect()
+}
+
+/// The board the bot expects after a move: the piece locked and full rows cleared.
+fn predict_board(state: &AppState, placement: &Placement) -> Vec<Vec<Option<Cell>>> {
+    let mut rows = state.board.as_rows();
+    for (row, col) in placement_cells(state, placement) {
+        if let Some(cell) = usize::try_from(row)
+            .ok()
+            .and_then(|row| rows.get_mut(row))
+            .and_then(|row| row.get_mut(col as usize))
+        {
+            *cell = Some(Cell::Block(placement.piece.piece));
+        }
+    }
+    rows.retain(|row| row.iter().any(Option::is_none));
+    let mut board = vec![vec![None; state.width()]; state.board.num_rows() - rows.len()];
+    board.append(&mut rows);
+    board
+}
+
+/// An external bot, running as a child process. The process is told to quit when this
+/// is dropped.
+#[derive(Debug)]
+pub struct TbpBot {
+    pub info: Info,
+    child: Child,
+    stdin: ChildStdin,
+    incoming: Receiver<Result<BotMessage, TbpError>>,
+    /// A game has been sent with `start` and not yet stopped.
+    started: bool,
+    /// A `suggest` was sent and its answer hasn't arrived yet.
+    thinking: bool,
+}
+impl TbpBot {
+    /// Starts the bot and waits until it says it is ready to play.
+    pub fn spawn(command: &mut Command) -> Result<Self, TbpError> {
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let stdin = child.stdin.take().expect("stdin was piped");
+        let stdout = child.stdout.take().expect("stdout was piped");
+        let (sender, incoming) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let message = match line {
+                    Ok(line) => serde_json::from_str(&line).map_err(TbpError::Json),
+                    Err(err) => Err(TbpError::Io(err)),
+                };
+                let failed = message.is_err();
+                if sender.send(message).is_err() || failed {
+                    return;
+                }
+            }
+            let _ = sender.send(Err(TbpError::Exited));
+        });
+
+        let mut bot = TbpBot {
+            info: Info {
+                name: String::new(),
+                version: String::new(),
+                author: String::new(),
+            },
+            child,
+            stdin,
+            incoming,
+            started: false,
+            thinking: false,
+        };
+        match bot.receive(true)? {
+            Some(BotMessage::Info(info)) => bot.info = info,
+            Some(message) => return Err(TbpError::Protocol(message.name().to_string())),
+            None => unreachable!("waiting always returns a message"),
+        }
+        bot.send(&FrontendMessage::Rules)?;
+        match bot.receive(true)? {
+            Some(BotMessage::Ready) => Ok(bot),
+            Some(BotMessage::Error { reason }) => Err(TbpError::Unsupported(reason)),
+            Some(message) => Err(TbpError::Protocol(message.name().to_string())),
+            None => unreachable!("waiting always returns a message"),
+        }
+    }
+
+    /// Tells the bot to forget what it knows and play from `state` instead.
+    pub fn start(&mut self, state: &AppState) -> Result<(), TbpError> {
+        if state.width() != COLS || state.board.num_rows() > BOARD_ROWS {
+            return Err(TbpError::BoardSize);
+        }
+        if *state.config.pieces != *PieceSet::tetrominoes() {
+            return Err(TbpError::Pieces);
+        }
+        // Bots answer every suggest, so an outstanding one is waited out and thrown away
+        if self.thinking {
+            self.thinking = false;
+            self.skip_suggestion()?;
+        }
+        if self.started {
+            self.send(&FrontendMessage::Stop)?;
+        }
+        self.send(&FrontendMessage::Start(Start::of(state)))?;
+        self.started = true;
+        Ok(())
+    }
+
+    /// Asks the bot for a move and plays it, waiting as long as the bot takes.
+    pub fn play(&mut self, state: &mut AppState) -> Result<Vec<Event>, TbpError> {
+        loop {
+            if let Some(events) = self.play_turn(state, true)? {
+                return Ok(events);
+            }
+        }
+    }
+
+    /// Plays the bot's move if it has decided on one, or asks it to start thinking
+    /// otherwise. Never blocks, so it can be called every frame.
+    pub fn try_play(&mut self, state: &mut AppState) -> Result<Option<Vec<Event>>, TbpError> {
+        self.play_turn(state, false)
+    }
+
+    fn play_turn(
+        &mut self,
+        state: &mut AppState,
+        wait: bool,
+    ) -> Result<Option<Vec<Event>>, TbpError> {
+        if state.game_over {
+            return Ok(Some(Vec::new()));
+        }
+        if !self.started {
+            self.start(state)?;
+        }
+        if !self.thinking {
+            self.send(&FrontendMessage::Suggest)?;
+            self.thinking = true;
+        }
+        match self.receive(wait)? {
+            None => Ok(None),
+            Some(BotMessage::Suggestion { moves }) => {
+                self.thinking = false;
+                self.apply(state, &moves).map(Some)
+            }
+            Some(message) => Err(TbpError::Protocol(message.name().to_string())),
+        }
+    }
+
+    /// Plays the first of `moves` the game can reach, and tells the bot what changed.
+    fn apply(&mut self, state: &mut AppState, moves: &[Move]) -> Result<Vec<Event>, TbpError> {
+        let placements = Bot::default().placements(state);
+        let (mv, placement) = moves
+            .iter()
+            .find_map(|mv| {
+                let cells = mv.location.cells(state.board.num_rows());
+                // Placements without hold come first, so a move for the current piece
+                // never holds when the held piece is the same
+                placements
+                    .iter()
+                    .find(|placement| {
+                        placement.piece.piece == mv.location.piece
+                            && placement_cells(state, placement) == cells
+                    })
+                    .map(|placement| (mv, placement))
+            })
+            .ok_or(TbpError::NoMove)?;
+        // Holding into an empty hold uses up the next piece as well
+        let held = placement.inputs.first() == Some(&StateChange::HoldPiece);
+        let consumed = if held && state.held.is_none() { 2 } else { 1 };
+        let predicted = predict_board(state, placement);
+
+        let events = placement
+            .inputs
+            .iter()
+            .flat_map(|change| state.apply(*change))
+            .collect();
+        self.send(&FrontendMessage::Play { mv: mv.clone() })?;
+        if state.game_over {
+            return Ok(events);
+        }
+        // Garbage the bot couldn't have known about means starting it over
+        if state.board.as_rows() != predicted {
+            self.start(state)?;
+        } else {
+            let revealed = state.queue.len().saturating_sub(consumed);
+            for piece in state
+                .queue
+                .iter()
+                .skip(revealed)
+                .copied()
+                .collect::<Vec<_>>()
+            {
+                self.send(&FrontendMessage::NewPiece { piece })?;
+            }
+        }
+        Ok(events)
+    }
+
+    fn skip_suggestion(&mut self) -> Result<(), TbpError> {
+        match self.receive(true)? {
+            Some(BotMessage::Suggestion { .. }) => Ok(()),
+            Some(message) => Err(TbpError::Protocol(message.name().to_string())),
+            None => unreachable!("waiting always returns a message"),
+        }
+    }
+
+    fn send(&mut self, message: &FrontendMessage) -> Result<(), TbpError> {
+        let line = serde_json::to_string(message)?;
+        writeln!(self.stdin, "{line}")?;
+        self.stdin.flush()?;
+        Ok(())
+    }
+
+    /// The next message that means something to us, or `None` if none has arrived and
+    /// `wait` is false.
+    fn receive(&mut self, wait: bool) -> Result<Option<BotMessage>, TbpError> {
+        loop {
+            let message = if wait {
+                self.incoming.recv().map_err(|_| TbpError::Exited)?
+            } else {
+                match self.incoming.try_recv() {
+                    Ok(message) => message,
+                    Err(TryRecvError::Empty) => return Ok(None),
+                    Err(TryRecvError::Disconnected) => return Err(TbpError::Exited),
+                }
+            };
+            match message? {
+                BotMessage::Unknown => {}
+                message => return Ok(Some(message)),
+            }
+        }
+    }
+}
+impl Drop for TbpBot {
+    fn drop(&mut self) {
+        let _ = self.send(&FrontendMessage::Quit);
+        // A bot that ignores quit would be left running, so it is killed and reaped
+        if let Ok(None) = self.child.try_wait() {
+            let _ = self.child.kill();
+        }
+        let _ = self.child.wait();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::Config;
+    use crate::tetromino::{Rotation, Tetromino};
+    use serde_json::Value;
+    use std::{
+        fs,
+        path::{Path, PathBuf},
+        time::Duration,
+    };
+
+    const PIECES: [Piece; 7] = [
+        Piece::I,
+        Piece::J,
+        Piece::L,
+        Piece::O,
+        Piece::S,
+        Piece::T,
+        Piece::Z,
+    ];
+    const ROTATIONS: [(Rotation, Orientation); 4] = [
+        (Rotation::Up, Orientation::North),
+        (Rotation::Right, Orientation::East),
+        (Rotation::Down, Orientation::South),
+        (Rotation::Left, Orientation::West),
+    ];
+
+    /// The TBP location of a placement, from the cell of the SRS box each piece turns
+    /// around, as `(row, col)`. I and O turn around a corner, so theirs moves with the
+    /// rotation.
+    fn location_of(state: &AppState, placement: &Placement) -> Location {
+        let turn = ROTATIONS
+            .iter()
+            .position(|(rotation, _)| *rotation == placement.piece.rotation)
+            .unwrap();
+        let (row, col) = match placement.piece.piece {
+            Piece::I => [(1, 1), (1, 2), (2, 2), (2, 1)][turn],
+            Piece::O => [(1, 0), (0, 0), (0, 1), (1, 1)][turn],
+            _ => (1, 1),
+        };
+        let (x, y) = placement.location;
+        Location {
+            piece: placement.piece.piece,
+            orientation: ROTATIONS[turn].1,
+            x: x + col,
+            y: state.board.num_rows() as isize - 1 - (y + row),
+        }
+    }
+
+    #[test]
+    fn locations_cover_the_same_cells_as_placements() {
+        let state = AppState::from_seed(Config::default(), 0);
+        for piece in PIECES {
+            for (rotation, _) in ROTATIONS {
+                let placement = Placement {
+                    inputs: Vec::new(),
+                    piece: Tetromino { piece, rotation },
+                    location: (3, 30),
+                    score: 0.0,
+                };
+                let location = location_of(&state, &placement);
+                assert_eq!(
+                    location.cells(state.board.num_rows()),
+                    placement_cells(&state, &placement),
+                    "{location:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn start_sends_the_board_bottom_row_first() {
+        let mut state = AppState::from_seed(Config::default(), 0);
+        let bottom = state.board.num_rows() - 1;
+        state.board[(bottom, 0)] = Some(Cell::Block(Piece::L));
+        state.board[(bottom, 1)] = Some(Cell::Garbage);
+        state.board[(bottom - 1, 9)] = Some(Cell::Block(Piece::T));
+        state.combo = Some(1);
+        let start = Start::of(&state);
+        assert_eq!(start.board.len(), BOARD_ROWS);
+        assert_eq!(start.board[0][..3], [Some('L'), Some('G'), None]);
+        assert_eq!(start.board[1][9], Some('T'));
+        assert!(start.board[2..].iter().flatten().all(Option::is_none));
+        assert_eq!(start.queue[0], state.piece.piece);
+        assert_eq!(
+            start.queue[1..],
+            state.queue.iter().copied().collect::<Vec<_>>()
+        );
+        assert_eq!(start.combo, 2);
+    }
+
+    /// A bot that logs every message it gets to `$DIR/log` and answers each suggest
+    /// with whatever is in `$DIR/suggestion` at the time.
+    const FAKE_BOT: &str = r#"
+        echo '{"type":"info","name":"fake","version":"1","author":"tests"}'
+        while read -r line; do
+            echo "$line" >> "$DIR/log"
+            case "$line" in
+                *'"type":"rules"'*) echo '{"type":"ready"}' ;;
+                *'"type":"suggest"'*) cat "$DIR/suggestion" ;;
+            esac
+        done
+    "#;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("tetris-tbp-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Has the bot suggest `placement` and plays it. Returns the suggested move.
+    fn play(bot: &mut TbpBot, dir: &Path, state: &mut AppState, placement: &Placement) -> Value {
+        let mv = serde_json::to_value(Move {
+            location: location_of(state, placement),
+            spin: Spin::None,
+        })
+        .unwrap();
+        let suggestion = serde_json::json!({ "type": "suggestion", "moves": [mv] });
+        fs::write(dir.join("suggestion"), format!("{suggestion}\n")).unwrap();
+        bot.play(state).unwrap();
+        mv
+    }
+
+    /// The first `count` messages the bot logged, waiting for them to arrive.
+    fn sent(dir: &Path, count: usize) -> Vec<Value> {
+        for _ in 0..500 {
+            let log = fs::read_to_string(dir.join("log")).unwrap_or_default();
+            if log.lines().count() >= count {
+                return log
+                    .lines()
+                    .take(count)
+                    .map(|line| serde_json::from_str(line).unwrap())
+                    .collect();
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        panic!("the bot never got {count} messages");
+    }
+
+    fn types(messages: &[Value]) -> Vec<&str> {
+        messages
+            .iter()
+            .map(|message| message["type"].as_str().unwrap())
+            .collect()
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn bots_are_told_about_moves_holds_and_garbage() {
+        let dir = scratch_dir("session");
+        let mut bot =
+            TbpBot::spawn(Command::new("sh").args(["-c", FAKE_BOT]).env("DIR", &dir)).unwrap();
+        assert_eq!(bot.info.name, "fake");
+        let mut state = AppState::from_seed(Config::default(), 0);
+
+        // A plain move reveals one new preview piece
+        let placement = Bot::default().placements(&state).remove(0);
+        assert_eq!(placement.inputs.first(), Some(&StateChange::HardDrop));
+        let mv = play(&mut bot, &dir, &mut state, &placement);
+        let messages = sent(&dir, 5);
+        assert_eq!(
+            types(&messages),
+            ["rules", "start", "suggest", "play", "new_piece"]
+        );
+        assert_eq!(messages[3]["move"], mv);
+        let last = state.queue.back().unwrap().to_char().to_string();
+        assert_eq!(messages[4]["piece"], last);
+
+        // Holding into an empty hold uses up two pieces, so two are revealed
+        let placement = Bot::default()
+            .placements(&state)
+            .into_iter()
+            .find(|placement| placement.inputs[0] == StateChange::HoldPiece)
+            .unwrap();
+        play(&mut bot, &dir, &mut state, &placement);
+        let messages = sent(&dir, 9);
+        assert_eq!(
+            types(&messages[5..]),
+            ["suggest", "play", "new_piece", "new_piece"]
+        );
+        let revealed = state
+            .queue
+            .iter()
+            .skip(state.queue.len() - 2)
+            .map(|piece| Value::from(piece.to_char().to_string()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            [&messages[7]["piece"], &messages[8]["piece"]],
+            [&revealed[0], &revealed[1]]
+        );
+
+        // Garbage the bot couldn't predict starts it over on the real board
+        state.receive_garbage(2);
+        let placement = Bot::default().placements(&state).remove(0);
+        play(&mut bot, &dir, &mut state, &placement);
+        let messages = sent(&dir, 13);
+        assert_eq!(types(&messages[9..]), ["suggest", "play", "stop", "start"]);
+        let board = &messages[12]["board"];
+        assert_eq!(
+            board[0]
+                .as_array()
+                .unwrap()
+                .iter()
+                .filter(|cell| *cell == "G")
+                .count(),
+            COLS - 1
+        );
+        assert_eq!(
+            messages[12]["hold"],
+            state.held.unwrap().to_char().to_string()
+        );
+
+        drop(bot);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn bots_that_ignore_quit_are_killed() {
+        let script = r#"
+            echo '{"type":"info","name":"stubborn","version":"1","author":"tests"}'
+            read -r line
+            echo '{"type":"ready"}'
+            exec sleep 60
+        "#;
+        let bot = TbpBot::spawn(Command::new("sh").args(["-c", script])).unwrap();
+        let pid = bot.child.id().to_string();
+        drop(bot);
+        let alive = Command::new("kill").args(["-0", &pid]).status().unwrap();
+        assert!(!alive.success(), "bot {pid} is still running");
+    }
+}
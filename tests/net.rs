@@ -0,0 +1,139 @@
+//! Two engines playing each other over TCP on the loopback interface.
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    thread,
+    time::{Duration, Instant},
+};
+use tetris::{
+    AppState, Cell, Config, Connection, Message, NetError, Snapshot, StateChange, PROTOCOL_VERSION,
+};
+
+fn connect() -> (Connection, Connection) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let joiner = thread::spawn(move || Connection::join(address).unwrap());
+    let host = Connection::host(&listener).unwrap();
+    (host, joiner.join().unwrap())
+}
+
+/// Waits for the next message, failing the test if none arrives.
+fn receive(connection: &mut Connection) -> Message {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    loop {
+        if let Some(message) = connection.poll().unwrap().into_iter().next() {
+            return message;
+        }
+        assert!(Instant::now() < deadline, "no message arrived");
+        thread::sleep(Duration::from_millis(1));
+    }
+}
+
+fn garbage_rows(state: &AppState) -> usize {
+    state
+        .board
+        .rows_iter()
+        .filter(|row| row.clone().any(|cell| *cell == Some(Cell::Garbage)))
+        .count()
+}
+
+#[test]
+fn boards_and_garbage_cross_the_connection() {
+    let (mut host, mut guest) = connect();
+    let mut host_game = AppState::from_seed(Config::default(), 1);
+    let mut guest_game = AppState::from_seed(Config::default(), 2);
+    // What each side shows of the other
+    let mut host_view = AppState::from_seed(Config::default(), 0);
+    let mut guest_view = AppState::from_seed(Config::default(), 0);
+
+    for _ in 0..3 {
+        host_game.apply(StateChange::HardDrop);
+    }
+    host.send(&Message::State(Snapshot::of(&host_game)))
+        .unwrap();
+    match receive(&mut guest) {
+        Message::State(snapshot) => snapshot.apply_to(&mut guest_view),
+        message => panic!("expected a state, got {message:?}"),
+    }
+    assert_eq!(guest_view.board, host_game.board);
+    assert_eq!(guest_view.piece, host_game.piece);
+    assert_eq!(guest_view.location, host_game.location);
+    assert_eq!(guest_view.queue, host_game.queue);
+    assert_eq!(Snapshot::of(&guest_view), Snapshot::of(&host_game));
+
+    host.send(&Message::Garbage(3)).unwrap();
+    match receive(&mut guest) {
+        Message::Garbage(lines) => guest_game.receive_garbage(lines),
+        message => panic!("expected garbage, got {message:?}"),
+    }
+    guest_game.apply(StateChange::HardDrop);
+    assert_eq!(garbage_rows(&guest_game), 3);
+
+    guest
+        .send(&Message::State(Snapshot::of(&guest_game)))
+        .unwrap();
+    match receive(&mut host) {
+        Message::State(snapshot) => snapshot.apply_to(&mut host_view),
+        message => panic!("expected a state, got {message:?}"),
+    }
+    assert_eq!(garbage_rows(&host_view), 3);
+    assert_eq!(host_view.board, guest_game.board);
+
+    guest.send(&Message::GameOver).unwrap();
+    assert_eq!(receive(&mut host), Message::GameOver);
+    host.send(&Message::Restart).unwrap();
+    assert_eq!(receive(&mut guest), Message::Restart);
+}
+
+#[test]
+fn closing_one_side_disconnects_the_other() {
+    let (host, mut guest) = connect();
+    drop(host);
+    let deadline = Instant::now() + Duration::from_secs(5);
+    loop {
+        match guest.poll() {
+            Err(NetError::Disconnected) => break,
+            Ok(messages) => assert!(messages.is_empty()),
+            Err(err) => panic!("unexpected error {err}"),
+        }
+        assert!(Instant::now() < deadline, "disconnect was not noticed");
+        thread::sleep(Duration::from_millis(1));
+    }
+}
+
+#[test]
+fn mismatched_versions_are_refused() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let imposter = thread::spawn(move || {
+        let mut stream = TcpStream::connect(address).unwrap();
+        writeln!(stream, "tetris-versus {}", PROTOCOL_VERSION + 1).unwrap();
+        // Hold the connection open until the host has read the greeting
+        let mut line = String::new();
+        BufReader::new(stream).read_line(&mut line).unwrap();
+    });
+    match Connection::host(&listener) {
+        Err(NetError::Version(version)) => assert_eq!(version, PROTOCOL_VERSION + 1),
+        other => panic!("expected a version error, got {other:?}"),
+    }
+    imposter.join().unwrap();
+}
+
+#[test]
+fn messages_survive_a_text_round_trip() {
+    let mut state = AppState::from_seed(Config::default(), 7);
+    state.receive_garbage(2);
+    state.apply(StateChange::HoldPiece);
+    state.apply(StateChange::HardDrop);
+    state.apply(StateChange::Rotate(tetris::MovementType::Right));
+    for message in [
+        Message::State(Snapshot::of(&state)),
+        Message::Garbage(4),
+        Message::GameOver,
+        Message::Restart,
+    ] {
+        assert_eq!(message.to_string().parse::<Message>().unwrap(), message);
+    }
+    assert!("state 1 2 3".parse::<Message>().is_err());
+    assert!("garbage lots".parse::<Message>().is_err());
+}
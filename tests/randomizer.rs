@@ -0,0 +1,182 @@
+//! How each randomizer's pieces are distributed, over long seeded runs.
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use std::collections::HashMap;
+use tetris::{
+    AppState, Bag, Config, History, Piece, PieceSet, Randomizer, RandomizerKind, TGM_HISTORY,
+    TGM_REROLLS,
+};
+
+/// Chi-squared with 6 degrees of freedom that a fair 7 piece randomizer exceeds one time
+/// in a thousand.
+const CHI_SQUARED_LIMIT: f64 = 22.46;
+
+fn deal(kind: RandomizerKind, seed: u64, count: usize) -> Vec<Piece> {
+    let mut randomizer = kind.build(PieceSet::tetrominoes().pieces());
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    (0..count).map(|_| randomizer.next(&mut rng)).collect()
+}
+
+fn counts(pieces: &[Piece]) -> HashMap<Piece, usize> {
+    let mut counts = HashMap::new();
+    for piece in pieces {
+        *counts.entry(*piece).or_default() += 1;
+    }
+    counts
+}
+
+/// Fails unless every standard piece comes up about equally often.
+fn assert_uniform(pieces: &[Piece]) {
+    let counts = counts(pieces);
+    let set = PieceSet::tetrominoes().pieces();
+    assert_eq!(
+        counts.len(),
+        set.len(),
+        "some pieces never came: {counts:?}"
+    );
+    let expected = pieces.len() as f64 / set.len() as f64;
+    let chi_squared = set
+        .iter()
+        .map(|piece| (counts[piece] as f64 - expected).powi(2) / expected)
+        .sum::<f64>();
+    assert!(
+        chi_squared < CHI_SQUARED_LIMIT,
+        "chi-squared {chi_squared:.1} for {counts:?}"
+    );
+}
+
+/// How often a piece is the same as the one before it.
+fn repeat_rate(pieces: &[Piece]) -> f64 {
+    let repeats = pieces.windows(2).filter(|pair| pair[0] == pair[1]).count();
+    repeats as f64 / (pieces.len() - 1) as f64
+}
+
+#[test]
+fn seven_bag_deals_each_piece_once_per_bag() {
+    let set = PieceSet::tetrominoes().pieces();
+    for seed in 0..20 {
+        for bag in deal(RandomizerKind::SevenBag, seed, 7 * 100).chunks(7) {
+            let counts = counts(bag);
+            assert!(
+                set.iter().all(|piece| counts.get(piece) == Some(&1)),
+                "{bag:?}"
+            );
+        }
+    }
+}
+
+#[test]
+fn fourteen_bag_deals_each_piece_twice_per_bag() {
+    let set = PieceSet::tetrominoes().pieces();
+    let pieces = deal(RandomizerKind::FourteenBag, 0, 14 * 1000);
+    for bag in pieces.chunks(14) {
+        let counts = counts(bag);
+        assert!(
+            set.iter().all(|piece| counts.get(piece) == Some(&2)),
+            "{bag:?}"
+        );
+    }
+    // Unlike the 7-bag, both copies can come back to back
+    assert!(repeat_rate(&pieces) > 0.05);
+}
+
+#[test]
+fn random_is_uniform_and_repeats_freely() {
+    let pieces = deal(RandomizerKind::Random, 0, 70_000);
+    assert_uniform(&pieces);
+    let rate = repeat_rate(&pieces);
+    assert!((rate - 1.0 / 7.0).abs() < 0.01, "repeat rate {rate}");
+}
+
+#[test]
+fn tgm_is_uniform_and_avoids_its_history() {
+    let pieces = deal(RandomizerKind::Tgm, 0, 70_000);
+    assert_uniform(&pieces);
+    // A piece from the history only comes after every roll failed to find another
+    let recent = pieces
+        .windows(TGM_HISTORY + 1)
+        .filter(|window| window[..TGM_HISTORY].contains(&window[TGM_HISTORY]))
+        .count() as f64
+        / pieces.len() as f64;
+    assert!(recent < 0.05, "history hit rate {recent}");
+    let rate = repeat_rate(&pieces);
+    assert!(rate < 0.02, "repeat rate {rate}");
+}
+
+#[test]
+fn tgm_rerolls_six_times() {
+    // With two pieces and one slot, a repeat needs the first roll and all six rerolls to
+    // hit the remembered piece: 1 in 128, where six rolls in total would be 1 in 64
+    let mut randomizer = History::new(vec![Piece::I, Piece::O], 1, TGM_REROLLS);
+    let mut rng = ChaCha8Rng::seed_from_u64(0);
+    let pieces = (0..200_000)
+        .map(|_| randomizer.next(&mut rng))
+        .collect::<Vec<_>>();
+    let rate = repeat_rate(&pieces);
+    assert!((rate - 1.0 / 128.0).abs() < 0.002, "repeat rate {rate}");
+}
+
+#[test]
+fn tgm_rarely_opens_with_s_or_z() {
+    let openers = (0..1000)
+        .map(|seed| deal(RandomizerKind::Tgm, seed, 1)[0])
+        .filter(|piece| [Piece::S, Piece::Z].contains(piece))
+        .count();
+    assert!(openers < 10, "{openers} of 1000 games opened with S or Z");
+}
+
+#[test]
+fn nes_is_uniform_and_rerolls_repeats_once() {
+    let pieces = deal(RandomizerKind::Nes, 0, 100_000);
+    assert_uniform(&pieces);
+    // A repeat needs the first roll to miss, 2 in 8, and the reroll to hit, 1 in 7
+    let rate = repeat_rate(&pieces);
+    assert!((rate - 1.0 / 28.0).abs() < 0.005, "repeat rate {rate}");
+}
+
+#[test]
+fn randomizers_deal_only_the_pieces_they_are_given() {
+    let pieces = vec![Piece::I, Piece::O];
+    for kind in [
+        RandomizerKind::SevenBag,
+        RandomizerKind::FourteenBag,
+        RandomizerKind::Random,
+        RandomizerKind::Tgm,
+        RandomizerKind::Nes,
+    ] {
+        let mut randomizer = kind.build(pieces.clone());
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let dealt = (0..1000)
+            .map(|_| randomizer.next(&mut rng))
+            .collect::<Vec<_>>();
+        assert!(dealt.iter().all(|piece| pieces.contains(piece)), "{kind}");
+        assert!(pieces.iter().all(|piece| dealt.contains(piece)), "{kind}");
+    }
+}
+
+#[test]
+fn cloned_randomizers_deal_the_same_pieces() {
+    let mut bag: Box<dyn Randomizer> = Box::new(Bag::new(PieceSet::tetrominoes().pieces(), 1));
+    let mut rng = ChaCha8Rng::seed_from_u64(0);
+    for _ in 0..3 {
+        bag.next(&mut rng);
+    }
+    let (mut copy, mut copy_rng) = (bag.clone(), rng.clone());
+    for _ in 0..20 {
+        assert_eq!(bag.next(&mut rng), copy.next(&mut copy_rng));
+    }
+}
+
+#[test]
+fn games_use_their_configured_randomizer() {
+    for kind in [RandomizerKind::FourteenBag, RandomizerKind::Nes] {
+        let config = Config {
+            randomizer: kind,
+            ..Default::default()
+        };
+        let state = AppState::from_seed(config, 7);
+        let mut expected = deal(kind, 7, state.queue.len() + 1).into_iter();
+        assert_eq!(Some(state.piece.piece), expected.next());
+        assert!(state.queue.iter().copied().eq(expected), "{kind}");
+    }
+}